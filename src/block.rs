@@ -1,14 +1,20 @@
 use std::borrow::Cow;
+use std::ops::RangeInclusive;
 
 use lazy_static::lazy_static;
 use fancy_regex::{Regex, Captures};
 
 use crate::regextra::fregex;
 use crate::htmltools::quoteattr;
-use crate::regex_snips::{SNIP_SPACE, SNIP_DIGIT, CLS_RE_S, VALIGN_RE_S, HALIGN_RE_S};
-use crate::htmltools::{generate_tag, encode_html};
+use crate::regex_snips::{SNIP_SPACE, SNIP_DIGIT, CLS_RE_S, VALIGN_RE_S, HALIGN_RE_S, CODE_LANG_RE};
+use crate::htmltools::{generate_tag, encode_html, split_html_lines, join_html_attributes, has_raw_text};
 use crate::parser::ParserState;
+use crate::table::process_data_table;
+use crate::media::{split_media_block, MediaKind};
+use crate::frontmatter::FrontMatter;
 
+/// The blocks containing paragraphs, other than `bq`
+pub(crate) const CONTAINER_BLOCK_TAGS: [&str; 5] = ["details", "div", "section", "aside", "figure"];
 
 #[derive(Default, Debug, Clone)]
 pub(crate) struct BlockHtmlAttributes (Vec<(String, String)>);
@@ -71,22 +77,28 @@ impl std::ops::Deref for BlockHtmlAttributes {
 }
 
 
-impl ToString for BlockHtmlAttributes {
-    fn to_string(&self) -> String {
-        let mut result = String::new();
+impl std::fmt::Display for BlockHtmlAttributes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for (key, value) in self.0.iter() {
-            result.push(' ');
-            result.push_str(key);
-            result.push('=');
-            result.push_str(&quoteattr(value));
+            write!(f, " {0}={1}", key, quoteattr(value))?;
         }
-        result
+        Ok(())
     }
 }
 
 
+/// Attributes of a Textile block, parsed from the part between its name
+/// and the dot, like `(class#id){color: red}[en]<` in `p(class#id){color: red}[en]<.`
+///
+/// A `String` made from it is ready to be inserted into an opening
+/// HTML tag:
+///
+/// ```rust
+/// # let attributes = rustextile::BlockAttributes::default();
+/// let opening_tag = format!("<div{0}>", String::from(attributes));
+/// ```
 #[derive(Debug, Clone, Default)]
-pub(crate) struct BlockAttributes {
+pub struct BlockAttributes {
     pub colspan: Option<String>,
     pub style: Option<String>,
     pub class: Option<String>,
@@ -98,7 +110,7 @@ pub(crate) struct BlockAttributes {
 }
 
 impl BlockAttributes {
-    pub fn parse(block_attributes: &str, element: Option<&str>, include_id: bool, restricted: bool) -> Self {
+    pub(crate) fn parse(block_attributes: &str, element: Option<&str>, include_id: bool, restricted: bool) -> Self {
         lazy_static! {
             static ref COLSPAN_RE: Regex = fregex!(r"\\(\d+)");
             static ref ROWSPAN_RE: Regex = fregex!(r"/(\d+)");
@@ -255,7 +267,12 @@ impl BlockAttributes {
         }
     }
 
-    pub fn html_attrs(self) -> BlockHtmlAttributes {
+    /// Returns the attributes as `(name, value)` pairs, sorted by name.
+    pub fn to_html_attributes(&self) -> Vec<(String, String)> {
+        self.clone().html_attrs().0
+    }
+
+    pub(crate) fn html_attrs(self) -> BlockHtmlAttributes {
         let mut chunks = BlockHtmlAttributes::default();
         chunks += ("class", self.class);
         chunks += ("colspan", self.colspan);
@@ -276,6 +293,162 @@ impl From<BlockAttributes> for String {
     }
 }
 
+/// Separates the declarations of the given `properties` from the style
+/// part of a block's attributes, like `numbers` in `bc{numbers: 10; color: red}.`,
+/// returning the rest of the attributes and the found `(property, value)` pairs.
+/// A property may be given without a value, which is then empty.
+pub(crate) fn extract_style_options<'a, 'p>(
+    attrs: &'a str,
+    properties: &[&'p str]
+) -> (Cow<'a, str>, Vec<(&'p str, &'a str)>) {
+    lazy_static! {
+        static ref STYLE_RE: Regex = fregex!(r"\{([^}]*)\}");
+    }
+    let m = match STYLE_RE.captures(attrs) {
+        Ok(Some(m)) => m,
+        _ => return (attrs.into(), Vec::new()),
+    };
+    let mut options = Vec::new();
+    let mut other_declarations = Vec::<&str>::new();
+    let declarations = m.get(1).map(|g| g.as_str()).unwrap_or_default();
+    for declaration in declarations.split(';') {
+        let (property, value) = match declaration.split_once(':') {
+            Some((p, v)) => (p.trim(), v.trim()),
+            None => (declaration.trim(), ""),
+        };
+        match properties.iter().find(|p| **p == property) {
+            Some(p) => options.push((*p, value)),
+            None if !declaration.trim().is_empty() => other_declarations.push(declaration),
+            None => {},
+        }
+    }
+    if options.is_empty() {
+        return (attrs.into(), options);
+    }
+    let whole = m.get(0).expect("Always present");
+    let style = if other_declarations.is_empty() {
+        String::new()
+    } else {
+        format!("{{{0}}}", other_declarations.join(";"))
+    };
+    let rest = format!("{0}{1}{2}", &attrs[..whole.start()], style, &attrs[whole.end()..]);
+    (rest.into(), options)
+}
+
+/// Line numbering and emphasized lines of `bc.` and `pre.` blocks.
+/// They are given among the style declarations of the block, like
+/// `bc[rust]{numbers: 10; lines: 12-14, 17}.`, where `numbers` enables
+/// numbering (optionally starting with the given number) and `lines`
+/// lists the emphasized lines, according to that numbering.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct CodeLayout {
+    first_number: Option<usize>,
+    emphasized: Vec<RangeInclusive<usize>>,
+}
+
+impl CodeLayout {
+    /// Separates the code layout options from the rest of the block's
+    /// attributes, which are returned for normal parsing.
+    /// Unlike the style declarations, the options are respected
+    /// in the restricted mode as well.
+    pub fn extract(attrs: &str) -> (Cow<'_, str>, Option<Self>) {
+        let (rest, options) = extract_style_options(attrs, &["numbers", "lines"]);
+        if options.is_empty() {
+            return (rest, None);
+        }
+        let mut layout = CodeLayout::default();
+        for (property, value) in options {
+            match property {
+                "numbers" => {
+                    layout.first_number = match value {
+                        "" | "on" | "yes" | "true" => Some(1),
+                        "off" | "no" | "false" => None,
+                        v => v.parse().ok(),
+                    };
+                },
+                _ => {
+                    layout.emphasized.extend(
+                        value.split(',').filter_map(|range| {
+                            let (start, end) = range.split_once('-').unwrap_or((range, range));
+                            let (start, end): (usize, usize) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+                            // A reversed range, like `5-3`, means the same lines
+                            Some(start.min(end)..=start.max(end))
+                        }));
+                },
+            }
+        }
+        (rest, Some(layout))
+    }
+
+    /// Wraps each line of the (already HTML-encoded or highlighted) code
+    /// into a `<span class="line">`, adding line numbers and marking
+    /// the emphasized lines if necessary.
+    pub fn render(&self, code_html: &str) -> String {
+        let first_number = self.first_number.unwrap_or(1);
+        let lines: Vec<String> = split_html_lines(code_html)
+            .into_iter()
+            .enumerate()
+            .map(|(index, line)| {
+                // The numbering stops where it would overflow
+                let number = first_number.checked_add(index);
+                let is_emphasized = number.is_some_and(|n| self.emphasized.iter().any(|r| r.contains(&n)));
+                let class = if is_emphasized { "line highlighted" } else { "line" };
+                let line_number = match (self.first_number, number) {
+                    (Some(_), Some(number)) => format!("<span class=\"line-number\">{0}</span>", number),
+                    _ => String::new(),
+                };
+                format!("<span class=\"{0}\">{1}{2}</span>", class, line_number, line)
+            })
+            .collect();
+        lines.join("\n")
+    }
+}
+
+/// Everything known about a block with a custom signature, which has been
+/// registered by [`Textile::register_block`](crate::Textile::register_block).
+#[derive(Debug)]
+pub struct CustomBlock<'a> {
+    /// The name of the block, like `note` for `note(important). Text`
+    pub signature: &'a str,
+    /// The attributes given between the signature and the dot, like
+    /// `(important)` in `note(important). Text`. Their values are not
+    /// HTML-escaped: that's done once they're turned into a `String`
+    /// for a tag. In the restricted mode the classes, the id and
+    /// the `{style}` part are left out.
+    pub attributes: &'a BlockAttributes,
+    /// An optional citation URL, like in `bq.:https://example.com Text`
+    pub cite: Option<&'a str>,
+    /// Content of the block. For the extended blocks (`note.. Text`)
+    /// each of the paragraphs is passed separately.
+    /// Beware that in the restricted mode the content is already HTML-encoded.
+    pub content: &'a str,
+    /// Whether this is an extended block (the one with two dots)
+    pub extended: bool,
+    /// The [front matter](crate::Textile::set_front_matter) of the document
+    pub front_matter: &'a FrontMatter,
+}
+
+/// What a custom block handler wants to be done with the block.
+#[derive(Debug, Clone)]
+pub enum CustomBlockOutput {
+    /// A ready piece of HTML which will be inserted as is. For the extended
+    /// blocks the HTML of each paragraph is placed one after another.
+    Html(String),
+    /// Asks the parser to process the content as any other Textile text,
+    /// and to wrap it into an element with the given name and attributes.
+    /// Just like with blockquotes, the text itself is wrapped into `<p>`
+    /// (one per each paragraph of an extended block).
+    ///
+    /// Only the given attributes are used: the class, id, style and
+    /// language of the block itself are not applied automatically.
+    /// To keep them, include
+    /// [`BlockAttributes::to_html_attributes`] into the list.
+    Textile {
+        element: String,
+        attributes: Vec<(String, String)>,
+    },
+}
+
 #[derive(Debug)]
 pub(crate) struct Block {
     pub outer_opening: String,
@@ -292,17 +465,23 @@ impl Block {
         attrs: &str,
         cite: Option<S>,
         content: &str,
+        extended: bool,
         ps: &mut ParserState
     ) -> Self
         where S: AsRef<str>
     {
         lazy_static! {
             static ref FNID_RE: Regex = fregex!(&format!(r"fn(?P<fnid>{0}+)", SNIP_DIGIT));
-            static ref CODE_LANG_RE: Regex = fregex!(r"^[a-zA-Z0-9_-]+$");
         }
         let cite = cite.map(|v| v.as_ref().to_owned());
         let mut new_content = Cow::Borrowed(content);
         let mut eat = false;
+        let (attrs, code_layout) = if tag == "bc" || tag == "pre" {
+            CodeLayout::extract(attrs)
+        } else {
+            (attrs.into(), None)
+        };
+        let attrs = attrs.as_ref();
         let mut attributes = BlockAttributes::parse(attrs, None, true, ps.textile.restricted);
         let orig_html_attributes = attributes.clone().html_attrs();
 
@@ -382,7 +561,35 @@ impl Block {
             tag
         };
 
+        let mut is_container = CONTAINER_BLOCK_TAGS.contains(&new_tag)
+            && !ps.textile.custom_blocks.contains_key(new_tag);
         match new_tag {
+            // Goes first, so a registered block would take precedence
+            // over an optional built-in one, like `math`
+            custom if ps.textile.custom_blocks.contains_key(custom) => {
+                let handler = &ps.textile.custom_blocks[custom];
+                let output = handler(&CustomBlock {
+                    signature: custom,
+                    attributes: &attributes,
+                    cite: cite.as_deref(),
+                    content: &new_content,
+                    extended,
+                    front_matter: &ps.front_matter,
+                });
+                match output {
+                    CustomBlockOutput::Html(html) => {
+                        new_content = ps.shelve(html).into();
+                    },
+                    CustomBlockOutput::Textile { element, attributes } => {
+                        let mut open_tag = String::new();
+                        join_html_attributes(&mut open_tag, &attributes);
+                        outer_opening = format!("<{0}{1}>\n", element, open_tag);
+                        inner_opening = "\t<p>".into();
+                        inner_closing = "</p>".into();
+                        outer_closing = format!("\n</{0}>", element);
+                    },
+                }
+            },
             "bq" => {
                 let mut html_attributes = attributes.html_attrs();
                 if let Some(ref cite) = cite {
@@ -390,17 +597,84 @@ impl Block {
                         ps.unrestrict_url(cite.as_str()).into());
                     html_attributes.insert("cite", shelved_url);
                 }
-                outer_opening = format!("<blockquote{0}>\n", html_attributes.to_string());
-                inner_opening = format!("\t<p{0}>", orig_html_attributes.to_string());
+                outer_opening = format!("<blockquote{0}>\n", html_attributes);
+                inner_opening = format!("\t<p{0}>", orig_html_attributes);
                 inner_closing = "</p>".into();
                 outer_closing = "\n</blockquote>".into();
             },
+            "details" if ps.textile.details => {
+                let summary = cite.as_deref().map(str::trim).unwrap_or_default();
+                let summary = if summary.is_empty() {
+                    String::new()
+                } else {
+                    format!("\t<summary>{0}</summary>\n", ps.graf(summary))
+                };
+                outer_opening = format!("<details{0}>\n{1}", attributes.html_attrs(), summary);
+                if !new_content.trim().is_empty() {
+                    inner_opening = "\t<p>".into();
+                    inner_closing = "</p>".into();
+                }
+                outer_closing = "\n</details>".into();
+            },
+            "fig" if ps.textile.figures => if let Some((img, alignment, caption)) = ps.figure_image(&new_content) {
+                if let Some(alignment) = alignment {
+                    let align_class = format!("align-{0}", alignment);
+                    attributes.class = Some(match attributes.class {
+                        Some(ref class) => format!("{0} {1}", class, align_class),
+                        None => align_class,
+                    });
+                }
+                let mut label = String::new();
+                if let Some(ref figure_label) = ps.textile.figure_label {
+                    ps.figure_count += 1;
+                    if attributes.id.is_none() {
+                        attributes.id = Some(format!("figure-{0}", ps.figure_count));
+                    }
+                    label = format!("{0} {1}", encode_html(figure_label, true, false), ps.figure_count);
+                    if !caption.is_empty() {
+                        label.push_str(". ");
+                    }
+                }
+                outer_opening = format!("<figure{0}>\n\t{1}\n", attributes.html_attrs(), img);
+                if !label.is_empty() || !caption.is_empty() {
+                    inner_opening = format!("\t<figcaption>{0}", label);
+                    inner_closing = "</figcaption>\n".into();
+                }
+                outer_closing = "</figure>".into();
+                new_content = caption.into();
+            } else {
+                // Without an image to start with, it's just a container
+                is_container = true;
+                outer_opening = format!("<figure{0}>\n", attributes.html_attrs());
+                inner_opening = "\t<p>".into();
+                inner_closing = "</p>".into();
+                outer_closing = "\n</figure>".into();
+            },
+            "video" | "audio" if ps.textile.media => {
+                let kind = if new_tag == "video" { MediaKind::Video } else { MediaKind::Audio };
+                let (sources, title) = split_media_block(&new_content);
+                let poster = cite.as_deref().filter(|_| kind == MediaKind::Video);
+                let media = ps.media_html(kind, &sources, title, poster, attributes.html_attrs());
+                if let Some(media) = media {
+                    new_content = ps.shelve(media).into();
+                } else {
+                    inner_opening = format!("<p{0}>", orig_html_attributes);
+                    inner_closing = "</p>".into();
+                }
+            },
+            "div" | "section" | "aside" | "figure" if ps.textile.containers => {
+                outer_opening = format!("<{0}{1}>\n", new_tag, attributes.html_attrs());
+                inner_opening = "\t<p>".into();
+                inner_closing = "</p>".into();
+                outer_closing = format!("\n</{0}>", new_tag);
+            },
             "bc" => {
-                new_content = ps.shelve(encode_html(&new_content, true, false)).into();
                 let mut inner_atts = BlockHtmlAttributes::default();
+                let mut highlighted = None;
                 if let Some(lang) = attributes.lang.clone() {
                     attributes.lang = None;
                     if let Ok(true) = CODE_LANG_RE.is_match(&lang) {
+                        highlighted = ps.highlight_code(&lang, &new_content);
                         let code_attrs = BlockAttributes {
                             class: Some(lang),
                             ..Default::default()
@@ -408,23 +682,46 @@ impl Block {
                         inner_atts = code_attrs.html_attrs();
                     }
                 }
+                let mut code = highlighted.unwrap_or_else(
+                    || encode_html(&new_content, true, false));
+                if let Some(ref layout) = code_layout {
+                    code = layout.render(&code);
+                }
+                new_content = ps.shelve(code).into();
                 let outer_atts = attributes.html_attrs();
-                outer_opening = format!("<pre{}><code{}>", outer_atts.to_string(), inner_atts.to_string());
+                outer_opening = format!("<pre{}><code{}>", outer_atts, inner_atts);
                 outer_closing = "</code></pre>".into();
             }
             "pre" => {
-                new_content = ps.shelve(encode_html(&new_content, true, false)).into();
-                outer_opening = format!("<pre{}>", attributes.html_attrs().to_string());
+                let mut code = encode_html(&new_content, true, false);
+                if let Some(ref layout) = code_layout {
+                    code = layout.render(&code);
+                }
+                new_content = ps.shelve(code).into();
+                outer_opening = format!("<pre{}>", attributes.html_attrs());
                 outer_closing = "</pre>".into();
             },
             "notextile" => {
                 new_content = ps.shelve(new_content.into_owned()).into();
             },
+            "math" if ps.textile.math => {
+                let formula = ps.render_math(&new_content, true);
+                new_content = ps.shelve(formula).into();
+                attributes.class = Some(match attributes.class {
+                    Some(ref class) => format!("math {0}", class),
+                    None => "math".to_owned(),
+                });
+                outer_opening = format!("<div{0}>", attributes.html_attrs());
+                outer_closing = "</div>".into();
+            },
+            "csv" | "tsv" if ps.textile.data_tables => {
+                new_content = process_data_table(ps, new_tag, attrs, &new_content).into();
+            },
             "###" => {
                 eat = true;
             },
             _ => {
-                inner_opening = format!("<{}{}>", new_tag, attributes.html_attrs().to_string());
+                inner_opening = format!("<{}{}>", new_tag, attributes.html_attrs());
                 inner_closing = format!("</{}>", new_tag);
             }
         }
@@ -433,6 +730,11 @@ impl Block {
         } else {
             "".into()
         };
+        // The lists and the tables within the containers aren't paragraphs
+        if is_container && !has_raw_text(&new_content) {
+            inner_opening.clear();
+            inner_closing.clear();
+        }
         Block {
             outer_opening,
             outer_closing,
@@ -448,7 +750,7 @@ impl Block {
 
 #[cfg(test)]
 mod test {
-    use crate::block::BlockHtmlAttributes;
+    use crate::block::{BlockHtmlAttributes, CodeLayout};
 
     #[test]
     fn test_html_attributes_manipulation() {
@@ -463,4 +765,31 @@ mod test {
         assert!(!atts.insert_css_class("invalid/class/name"));
         assert_eq!(atts.to_string(), " class=\"align-left otherclass\" id=\"id-value&amp;data\"");
     }
+
+    #[test]
+    fn test_code_layout_extraction() {
+        let (rest, layout) = CodeLayout::extract("[rust]{lines: 3-5, 8, 12-10}");
+        assert_eq!(rest, "[rust]");
+        assert_eq!(layout, Some(CodeLayout { first_number: None, emphasized: vec![3..=5, 8..=8, 10..=12] }));
+
+        let (rest, layout) = CodeLayout::extract("(cls){color: red; numbers: 10}");
+        assert_eq!(rest, "(cls){color: red}");
+        assert_eq!(layout, Some(CodeLayout { first_number: Some(10), emphasized: vec![] }));
+
+        let (rest, layout) = CodeLayout::extract("{color: red}");
+        assert_eq!(rest, "{color: red}");
+        assert_eq!(layout, None);
+    }
+
+    #[test]
+    fn test_code_layout_numbering_overflow() {
+        let (_, layout) = CodeLayout::extract(&format!("{{numbers: {0}; lines: {0}}}", u64::MAX));
+        let layout = layout.unwrap();
+        assert_eq!(layout.first_number, Some(usize::MAX));
+        assert_eq!(
+            layout.render("a\nb"),
+            format!(concat!("<span class=\"line highlighted\"><span class=\"line-number\">{0}</span>a</span>\n",
+                            "<span class=\"line\">b</span>"),
+                    usize::MAX));
+    }
 }
@@ -0,0 +1,34 @@
+//! The detailed result of parsing, returned by
+//! [`Textile::parse_document`](crate::Textile::parse_document).
+
+use crate::entities::Entity;
+use crate::frontmatter::FrontMatter;
+use crate::table::{Table, TableDiagnostic};
+use crate::tasks::Task;
+
+/// The HTML produced from a Textile document, along with the things
+/// found in it along the way.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Document {
+    /// Same as what [`Textile::parse`](crate::Textile::parse) returns
+    pub html: String,
+    /// The mentions and hashtags recognized by the
+    /// [`EntityResolver`](crate::EntityResolver), in the order of their
+    /// first appearance, without repetitions.
+    pub entities: Vec<Entity>,
+    /// The tables, in the order of their appearance
+    pub tables: Vec<Table>,
+    /// The problems found in the tables, such as the ones fixed when
+    /// [`Textile::set_normalize_tables`](crate::Textile::set_normalize_tables)
+    /// is enabled, in the order of their appearance
+    pub table_diagnostics: Vec<TableDiagnostic>,
+    /// The items of the task lists, in the order of their appearance, if
+    /// [`Textile::set_task_lists`](crate::Textile::set_task_lists)
+    /// is enabled
+    pub tasks: Vec<Task>,
+    /// The metadata header of the document, if
+    /// [`Textile::set_front_matter`](crate::Textile::set_front_matter)
+    /// is enabled
+    pub front_matter: FrontMatter,
+}
@@ -0,0 +1,11 @@
+//! Custom emoji for the shortcodes like `:parrot:`.
+
+/// What a custom emoji shortcode, added by
+/// [`Textile::add_emoji`](crate::Textile::add_emoji), turns into.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CustomEmoji {
+    /// Some text, usually a Unicode emoji. It goes into the HTML encoded.
+    Text(String),
+    /// An image with the given URL.
+    Image(String),
+}
@@ -0,0 +1,58 @@
+//! Mentions (`@alice`) and hashtags (`#release-2`) within the text.
+
+/// Tells a mention from a hashtag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EntityKind {
+    /// `@name`
+    Mention,
+    /// `#name`
+    Hashtag,
+}
+
+/// A mention or a hashtag found in the text and recognized
+/// by the [`EntityResolver`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entity {
+    pub kind: EntityKind,
+    /// The name, without the leading `@` or `#`
+    pub name: String,
+    /// The URL the entity has been linked to
+    pub url: String,
+}
+
+/// A hook deciding which mentions and hashtags exist, and where they lead.
+///
+/// It receives the name without the leading `@` or `#` and returns
+/// the URL of the entity's page, or `None` if there's no such entity,
+/// in which case the text is left as it is. The resolver may be asked
+/// about the same name more than once during parsing.
+///
+/// Any closure with the matching signature works as a resolver:
+///
+/// ```rust
+/// use rustextile::{Textile, EntityKind};
+/// let textile = Textile::default()
+///     .set_entity_resolver(|kind: EntityKind, name: &str| {
+///         match kind {
+///             EntityKind::Mention if name == "alice" => Some("/users/alice".to_owned()),
+///             EntityKind::Hashtag => Some(format!("/tags/{0}", name)),
+///             _ => None,
+///         }
+///     });
+/// let html = textile.parse("@alice and @bob tagged it #rust");
+/// assert_eq!(
+///     html,
+///     concat!(r#"<p><a class="mention" href="/users/alice">@alice</a> and @bob tagged it "#,
+///             r#"<a class="hashtag" href="/tags/rust">#rust</a></p>"#));
+/// ```
+pub trait EntityResolver {
+    fn resolve(&self, kind: EntityKind, name: &str) -> Option<String>;
+}
+
+impl<F> EntityResolver for F
+    where F: Fn(EntityKind, &str) -> Option<String>
+{
+    fn resolve(&self, kind: EntityKind, name: &str) -> Option<String> {
+        self(kind, name)
+    }
+}
@@ -0,0 +1,136 @@
+//! The metadata header of a document, like `title: Hello`,
+//! recognized if [`Textile::set_front_matter`](crate::Textile::set_front_matter)
+//! is enabled.
+
+use indexmap::IndexMap;
+
+/// The values of the front matter by their keys, in the order they are
+/// written. A key given more than once keeps the last value.
+pub type FrontMatter = IndexMap<String, FrontMatterValue>;
+
+/// A value of the [`FrontMatter`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrontMatterValue {
+    /// A single value, like `title: Hello`, without the enclosing quotes
+    Text(String),
+    /// A list, either like `tags: [news, rust]` or with its items
+    /// on the following lines, each starting with `- `
+    List(Vec<String>),
+}
+
+impl FrontMatterValue {
+    /// The text of a single value
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            Self::List(_) => None,
+        }
+    }
+
+    /// The items of a list
+    pub fn as_list(&self) -> Option<&[String]> {
+        match self {
+            Self::Text(_) => None,
+            Self::List(items) => Some(items),
+        }
+    }
+}
+
+/// Parses the front matter the text starts with, between the `---` lines,
+/// returning it along with the offset of the text following it.
+/// Unless every line of it is either a `key: value`, a list item,
+/// a comment or blank, it's not considered the front matter.
+pub(crate) fn split_front_matter(text: &str) -> Option<(FrontMatter, usize)> {
+    let mut lines = text.split_inclusive('\n');
+    let mut offset = 0;
+    let opening = lines.next()?;
+    if opening.trim_end() != "---" {
+        return None;
+    }
+    offset += opening.len();
+
+    let mut front_matter = FrontMatter::new();
+    // The key which may be followed by the items of its list
+    let mut list_key: Option<String> = None;
+    for line in lines {
+        offset += line.len();
+        let line = line.trim_end();
+        if line == "---" || line == "..." {
+            return Some((front_matter, offset));
+        }
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if let Some(item) = trimmed.strip_prefix('-').filter(|i| i.is_empty() || i.starts_with(' ')) {
+            let item = unquote(item.trim()).to_owned();
+            let value = front_matter.get_mut(list_key.as_ref()?)?;
+            match value {
+                FrontMatterValue::List(items) => items.push(item),
+                FrontMatterValue::Text(_) => *value = FrontMatterValue::List(vec![item]),
+            }
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let (key, value) = line.split_once(':')?;
+        let key = key.trim_end();
+        if key.is_empty() || !value.is_empty() && !value.starts_with(' ') {
+            return None;
+        }
+        let value = value.trim();
+        if value.is_empty() {
+            // Either an empty value or the start of a list
+            front_matter.insert(key.to_owned(), FrontMatterValue::Text(String::new()));
+            list_key = Some(key.to_owned());
+            continue;
+        }
+        list_key = None;
+        let value = match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            Some(items) => FrontMatterValue::List(
+                items.split(',')
+                    .map(|item| unquote(item.trim()).to_owned())
+                    .filter(|item| !item.is_empty())
+                    .collect()),
+            None => FrontMatterValue::Text(unquote(value).to_owned()),
+        };
+        front_matter.insert(key.to_owned(), value);
+    }
+    // No closing line
+    None
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(unquoted) = value.strip_prefix(quote).and_then(|v| v.strip_suffix(quote)) {
+            return unquoted;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split_front_matter, FrontMatterValue};
+
+    #[test]
+    fn test_split_front_matter() {
+        let text = concat!(
+            "---\ntitle: \"Hello: world\"\n# A comment\ntags:\n  - news\n  - 'rust'\n",
+            "draft:\nauthors: [Ann, \"Bob\"]\n---\nText");
+        let (front_matter, offset) = split_front_matter(text).unwrap();
+        assert_eq!(&text[offset..], "Text");
+        assert_eq!(front_matter["title"].as_text(), Some("Hello: world"));
+        assert_eq!(front_matter["tags"], FrontMatterValue::List(vec!["news".into(), "rust".into()]));
+        assert_eq!(front_matter["draft"].as_text(), Some(""));
+        assert_eq!(front_matter["authors"].as_list(), Some(&["Ann".to_owned(), "Bob".to_owned()][..]));
+        assert_eq!(front_matter.keys().collect::<Vec<_>>(), ["title", "tags", "draft", "authors"]);
+
+        assert!(split_front_matter("---\r\ntitle: Hi\r\n...\r\n").is_some());
+        assert!(split_front_matter("---\ntitle: Hi\n").is_none());
+        assert!(split_front_matter("---\nJust a line\n---\n").is_none());
+        assert!(split_front_matter("---\n- item\n---\n").is_none());
+        assert!(split_front_matter("Text\n---\ntitle: Hi\n---\n").is_none());
+    }
+}
@@ -0,0 +1,128 @@
+//! Configuration of the typographic replacements ("glyphs"), such as
+//! curly quotes, dashes or the `(C)` → © sign.
+
+use std::collections::HashSet;
+
+use fancy_regex::Regex;
+
+/// A single built-in glyph rule, which can be toggled in a [`GlyphSet`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum GlyphRule {
+    /// `4x4` → `4×4`
+    DimensionSign,
+    /// `it's` → `it’s`, `back in '88` → `back in ’88`
+    Apostrophes,
+    /// `'quoted'` → `‘quoted’`
+    SingleQuotes,
+    /// `"quoted"` → `“quoted”`
+    DoubleQuotes,
+    /// `...` → `…`
+    Ellipsis,
+    /// A lone `&` → `&amp;` (encoded in any case, when not a part of an entity)
+    Ampersand,
+    /// `--` → `—`
+    EmDash,
+    /// ` - ` → ` – `
+    EnDash,
+    /// `(TM)` → `™`
+    Trademark,
+    /// `(R)` → `®`
+    Registered,
+    /// `(C)` → `©`
+    Copyright,
+    /// `(1/2)`, `(1/4)`, `(3/4)` → `½`, `¼`, `¾`
+    Fractions,
+    /// `(o)` → `°`
+    Degrees,
+    /// `(+/-)` → `±`
+    PlusMinus,
+    /// `ABC(A Big Company)` → `<abbr title="A Big Company">ABC</abbr>`
+    Acronyms,
+    /// `NASA` → `<span class="caps">NASA</span>`
+    Caps,
+    /// No-break spaces before `;`, `:`, `!` and `?`, for the
+    /// [French](crate::TypographyLocale::French) typography only
+    PunctuationSpacing,
+}
+
+/// Tells the parser which glyph rules to apply, and which custom rules
+/// to add to them. Check [`Textile::set_glyph_set`](crate::Textile::set_glyph_set).
+///
+/// By default all the built-in rules are enabled.
+///
+/// Example:
+///
+/// ```rust
+/// use rustextile::{Textile, GlyphSet, GlyphRule};
+/// let glyphs = GlyphSet::default()
+///     .disable(GlyphRule::DimensionSign)
+///     .disable(GlyphRule::Caps)
+///     .add_rule(r"\b(\d+) ?EUR\b", "$1&#160;&#8364;");
+/// let textile = Textile::default().set_glyph_set(glyphs);
+/// let html = textile.parse("The 4x4 BMW costs 100 EUR (C)");
+/// assert_eq!(html, "<p>The 4x4 BMW costs 100&#160;&#8364; &#169;</p>");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct GlyphSet {
+    disabled: HashSet<GlyphRule>,
+    custom_rules: Vec<(Regex, String)>,
+}
+
+impl GlyphSet {
+    /// A set with no built-in rules enabled, to enable the needed ones
+    /// one by one.
+    pub fn none() -> Self {
+        use GlyphRule::*;
+        Self {
+            disabled: [
+                DimensionSign, Apostrophes, SingleQuotes, DoubleQuotes,
+                Ellipsis, Ampersand, EmDash, EnDash, Trademark, Registered,
+                Copyright, Fractions, Degrees, PlusMinus, Acronyms, Caps,
+                PunctuationSpacing,
+            ].into_iter().collect(),
+            custom_rules: Vec::new(),
+        }
+    }
+
+    pub fn enable(mut self, rule: GlyphRule) -> Self {
+        self.disabled.remove(&rule);
+        self
+    }
+
+    pub fn disable(mut self, rule: GlyphRule) -> Self {
+        self.disabled.insert(rule);
+        self
+    }
+
+    pub fn is_enabled(&self, rule: GlyphRule) -> bool {
+        !self.disabled.contains(&rule)
+    }
+
+    /// Adds a custom rule, replacing every match of the `pattern`
+    /// (a [fancy-regex](https://docs.rs/fancy-regex) expression) with
+    /// the `replacement`, which can refer to the captured groups as `$1`
+    /// or `$name`. The custom rules are applied after the built-in ones,
+    /// in the order they were added, to the text outside HTML tags.
+    ///
+    /// The rules see the text with `&`, `<` and `>` already HTML-encoded
+    /// (so an arrow `->` has to be matched as `-&gt;`), while the replacement
+    /// is inserted into the HTML as is. This way the captured groups can't
+    /// bring any markup in, even in the
+    /// [restricted](crate::Textile::set_restricted) mode, as long as the rule
+    /// doesn't turn the entities like `&lt;` back into the characters.
+    ///
+    /// # Panics
+    ///
+    /// If the pattern is not a valid regular expression.
+    pub fn add_rule(mut self, pattern: &str, replacement: &str) -> Self {
+        let pattern = Regex::new(pattern)
+            .unwrap_or_else(|e| panic!("Invalid glyph rule pattern {:?}: {}", pattern, e));
+        self.custom_rules.push((pattern, replacement.to_owned()));
+        self
+    }
+
+    pub(crate) fn custom_rules(&self) -> &[(Regex, String)] {
+        &self.custom_rules
+    }
+}
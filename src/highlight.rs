@@ -0,0 +1,112 @@
+//! Syntax highlighting support for code blocks (`bc[lang].`) and
+//! inline code (`@[lang]code@`).
+
+/// A hook that turns a piece of source code into highlighted HTML.
+///
+/// The parser calls it only for the code which has its language specified,
+/// like `bc[rust]. let x = 1;` or `@[rust]let x = 1;@`, and only when
+/// the language name looks sane (consists of latin letters, digits,
+/// `-` and `_`). The received code is raw, meaning it is not HTML-encoded
+/// yet, even in [restricted](crate::Textile::set_restricted) mode.
+///
+/// The returned HTML is inserted into the `<code>` element as is,
+/// so it is the highlighter's duty to escape the code properly.
+/// Returning `None` makes the parser fall back to the usual escaped output,
+/// which is handy for unknown languages. For the inline code, that output
+/// keeps the `[lang]` part, as it could be just the code starting with
+/// brackets, like `@[0]-based@`.
+///
+/// Any closure with the matching signature works as a highlighter:
+///
+/// ```rust
+/// use rustextile::Textile;
+/// let textile = Textile::default()
+///     .set_code_highlighter(|lang: &str, code: &str| {
+///         if lang == "shout" {
+///             Some(code.to_uppercase())
+///         } else {
+///             None
+///         }
+///     });
+/// let html = textile.parse("bc[shout]. hello");
+/// assert_eq!(html, r#"<pre><code class="shout">HELLO</code></pre>"#);
+/// ```
+pub trait CodeHighlighter {
+    fn highlight(&self, lang: &str, code: &str) -> Option<String>;
+}
+
+impl<F> CodeHighlighter for F
+    where F: Fn(&str, &str) -> Option<String>
+{
+    fn highlight(&self, lang: &str, code: &str) -> Option<String> {
+        self(lang, code)
+    }
+}
+
+
+/// A ready to use [`CodeHighlighter`] based on the
+/// [syntect](https://docs.rs/syntect) library. Requires the `syntect`
+/// feature to be enabled.
+///
+/// Instead of inline styles it produces `<span>`s with CSS classes,
+/// so the colors are up to the site's stylesheet, which can be generated
+/// from any Sublime Text theme by
+/// [`syntect::html::css_for_theme_with_class_style`].
+///
+/// Example:
+///
+/// ```rust
+/// use rustextile::{Textile, SyntectHighlighter};
+/// let textile = Textile::default()
+///     .set_code_highlighter(SyntectHighlighter::default());
+/// let html = textile.parse("bc[rust]. let x = 1;");
+/// assert!(html.starts_with(r#"<pre><code class="rust"><span class="source rust">"#));
+/// ```
+#[cfg(feature = "syntect")]
+pub struct SyntectHighlighter {
+    syntax_set: syntect::parsing::SyntaxSet,
+    class_style: syntect::html::ClassStyle,
+}
+
+#[cfg(feature = "syntect")]
+impl Default for SyntectHighlighter {
+    fn default() -> Self {
+        Self::new(syntect::parsing::SyntaxSet::load_defaults_newlines())
+    }
+}
+
+#[cfg(feature = "syntect")]
+impl SyntectHighlighter {
+    /// Creates a highlighter recognizing languages from the given set
+    /// of syntaxes. The [`Default`] implementation uses the syntaxes
+    /// bundled with syntect.
+    pub fn new(syntax_set: syntect::parsing::SyntaxSet) -> Self {
+        Self {
+            syntax_set,
+            class_style: syntect::html::ClassStyle::Spaced,
+        }
+    }
+
+    /// Changes how syntect names the CSS classes, for instance to add
+    /// a prefix to each of them (`ClassStyle::SpacedPrefixed`).
+    pub fn set_class_style(mut self, class_style: syntect::html::ClassStyle) -> Self {
+        self.class_style = class_style;
+        self
+    }
+}
+
+#[cfg(feature = "syntect")]
+impl CodeHighlighter for SyntectHighlighter {
+    fn highlight(&self, lang: &str, code: &str) -> Option<String> {
+        use syntect::html::ClassedHTMLGenerator;
+        use syntect::util::LinesWithEndings;
+
+        let syntax = self.syntax_set.find_syntax_by_token(lang)?;
+        let mut generator = ClassedHTMLGenerator::new_with_class_style(
+            syntax, &self.syntax_set, self.class_style);
+        for line in LinesWithEndings::from(code) {
+            generator.parse_html_for_line_which_includes_newline(line).ok()?;
+        }
+        Some(generator.finalize())
+    }
+}
@@ -44,7 +44,7 @@ pub(crate) fn encode_html(text: &str, quotes: bool, line_spacers: bool) -> Strin
     result
 }
 
-pub(crate) fn reverse_encode_html(text: &str) -> Cow<str> {
+pub(crate) fn reverse_encode_html(text: &str) -> Cow<'_, str> {
     lazy_static! {
         static ref ENTITY_RE: Regex = fregex!(
             "(&(?:amp|lt|gt|quot|#39|#13|#10|#9);)");
@@ -52,6 +52,7 @@ pub(crate) fn reverse_encode_html(text: &str) -> Cow<str> {
     ENTITY_RE.replace_all(text, |cap: &Captures| {
         let entity = &cap[1];
         match entity {
+            "&amp;" => "&",
             "&lt;" => "<",
             "&gt;" => ">",
             "&quot;" => "\"",
@@ -254,7 +255,7 @@ fn replace_charref(s: &Captures) -> String {
 /// A full equivalent of `html.unescape` from Python. Transforms a string
 /// by replacing "escaped" HTML characters (such as `&gt;`) into their original
 /// form (character `>` in this instance).
-pub(crate) fn unescape(s: &str) -> Cow<str> {
+pub(crate) fn unescape(s: &str) -> Cow<'_, str> {
     if !s.contains('&') {
         Cow::Borrowed(s)
     } else {
@@ -268,6 +269,15 @@ pub(crate) fn unescape(s: &str) -> Cow<str> {
     }
 }
 
+/// Same as [`unescape`], but only for the decimal character references,
+/// like `&#8217;`, leaving `&amp;` and the like as they are.
+pub(crate) fn unescape_numeric(s: &str) -> Cow<'_, str> {
+    lazy_static! {
+        static ref NUMERIC_CHARREF: Regex = fregex!(r"&(#[0-9]+;)");
+    }
+    NUMERIC_CHARREF.replace_all(s, replace_charref)
+}
+
 
 pub(crate) fn has_raw_text(text: &str) -> bool {
     const PHRASING_CONTENT: &str = concat!(
@@ -298,9 +308,64 @@ pub(crate) fn has_raw_text(text: &str) -> bool {
 }
 
 
+// The elements which have no closing tag, even when written without `/>`
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr"];
+
+/// Splits a piece of HTML (like an output of a code highlighter) into lines.
+/// Any elements left open at the end of a line are closed there
+/// and reopened at the start of the next one, so each line is
+/// a well-formed HTML fragment on its own.
+pub(crate) fn split_html_lines(html: &str) -> Vec<String> {
+    let mut lines = Vec::<String>::new();
+    let mut open_tags = Vec::<(&str, &str)>::new();
+    let mut line = String::new();
+    let mut rest = html;
+    while let Some(pos) = rest.find(['<', '\n']) {
+        line.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if rest.starts_with('\n') {
+            for (name, _) in open_tags.iter().rev() {
+                line.push_str("</");
+                line.push_str(name);
+                line.push('>');
+            }
+            lines.push(std::mem::take(&mut line));
+            for (_, opening) in open_tags.iter() {
+                line.push_str(opening);
+            }
+            rest = &rest[1..];
+            continue;
+        }
+        let tag_end = rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+        let tag = &rest[..tag_end];
+        line.push_str(tag);
+        rest = &rest[tag_end..];
+        if let Some(closing) = tag.strip_prefix("</") {
+            let name = closing.trim_end_matches('>').trim();
+            if let Some(index) = open_tags.iter().rposition(|(n, _)| n.eq_ignore_ascii_case(name)) {
+                open_tags.truncate(index);
+            }
+        } else if !tag.ends_with("/>") && !tag.starts_with("<!") {
+            let name_end = tag[1..]
+                .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .map(|i| i + 1)
+                .unwrap_or(tag.len());
+            let name = &tag[1..name_end];
+            if !VOID_ELEMENTS.iter().any(|void| void.eq_ignore_ascii_case(name)) {
+                open_tags.push((name, tag));
+            }
+        }
+    }
+    line.push_str(rest);
+    lines.push(line);
+    lines
+}
+
+
 #[cfg(test)]
 mod tests {
-    use super::{quoteattr, unescape, encode_html, has_raw_text};
+    use super::{quoteattr, unescape, unescape_numeric, encode_html, has_raw_text, split_html_lines};
 
     #[test]
     fn test_quoteattr() {
@@ -309,6 +374,12 @@ mod tests {
             "'So called \"escaped\"&#13;multi-line &lt;value&gt;'");
     }
 
+    #[test]
+    fn test_reverse_encode_html() {
+        let encoded = encode_html("<a href=\"?x=1&y=2\">", true, false);
+        assert_eq!(super::reverse_encode_html(&encoded), "<a href=\"?x=1&y=2\">");
+    }
+
     #[test]
     fn test_unescape() {
         let original = r#"<a href="http://example.com">Some&nbsp;link</a>"#;
@@ -318,9 +389,32 @@ mod tests {
         assert_eq!(unescaped, original);
     }
 
+    #[test]
+    fn test_unescape_numeric() {
+        assert_eq!(unescape_numeric("it&#8217;s &amp; &lt;&#215;&gt;"), "it’s &amp; &lt;×&gt;");
+    }
+
     #[test]
     fn test_has_raw_text() {
         assert!(!has_raw_text("<p>foo bar biz baz</p>"));
         assert!(has_raw_text(" why yes, yes it does"));
     }
+
+    #[test]
+    fn test_split_html_lines() {
+        assert_eq!(split_html_lines("one\ntwo"), ["one", "two"]);
+        assert_eq!(
+            split_html_lines("<span class=\"a\">x\n<b>y</b>\nz</span><br />\n"),
+            [
+                "<span class=\"a\">x</span>",
+                "<span class=\"a\"><b>y</b></span>",
+                "<span class=\"a\">z</span><br />",
+                "",
+            ]);
+        assert_eq!(
+            split_html_lines("<i>a<br>b
+c<img src=\"x.png\"></i>
+d"),
+            ["<i>a<br>b</i>", "<i>c<img src=\"x.png\"></i>", "d"]);
+    }
 }
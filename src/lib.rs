@@ -5,13 +5,22 @@
 //! [PHP Textile](https://github.com/textile/php-textile) implementation
 //! and supports all of its markup features (as of php-textile v3.7.7), including
 //!
-//! * Decorated text spans
-//! * Images
-//! * Tables
-//! * Ordered/unordered lists
+//! * Decorated text spans, plus a few optional [extra ones](#extra-inline-elements)
+//! * Images, [responsive](ResponsiveImages) ones too, and [figures](#figures) with captions
+//! * [Video and audio](#video-and-audio) embeds
+//! * Tables, also available as a [structured model](Table), and
+//!   [CSV/TSV data](#csv-and-tsv-tables) rendered as tables, optionally
+//!   [conforming to HTML5](Textile::set_html5_tables)
+//! * Ordered/unordered lists, and [task lists](Textile::set_task_lists),
+//!   with [blocks within the items](#blocks-within-list-items)
 //! * Definition lists
 //! * Complex quotations
-//! * Code blocks
+//! * [Containers](#containers) and [collapsible blocks](#collapsible-blocks)
+//! * Code blocks, with optional [syntax highlighting](Textile::set_code_highlighter)
+//! * Optional [math formulas](Textile::set_math)
+//! * Optional [emoji shortcodes](Textile::set_emoji)
+//! * Optional [mentions and hashtags](Textile::set_entity_resolver)
+//! * Optional [front matter](Textile::set_front_matter) for the document's metadata
 //! * CSS styles, classes and ID attributes
 //! * Raw HTML inserts
 //! * Footnotes and references
@@ -77,17 +86,185 @@
 //! assert_eq!(html, r#"Sanitizer can also be <a href="https://example.com/some-page/">tuned</a>"#);
 //!
 //! ````
+//!
+//! # Extra inline elements
+//!
+//! Besides the spans of the canonical Textile, the parser can recognize
+//! a few more once they're enabled by [`Textile::set_extra_spans`].
+//! They work the same way (including attributes and nesting)
+//! and are available in restricted mode too:
+//!
+//! | Markup             | HTML                           |
+//! |--------------------|--------------------------------|
+//! | `[[Ctrl+C]]`       | `<kbd>Ctrl+C</kbd>`            |
+//! | `%%highlighted%%`  | `<mark>highlighted</mark>`     |
+//! | `~~fine print~~`   | `<small>fine print</small>`    |
+//! | `''quotation''`    | `<q>quotation</q>`             |
+//!
+//! ```rust
+//! use rustextile::Textile;
+//! let textile = Textile::default().set_extra_spans(true);
+//! let html = textile.parse("Press [[(key)Ctrl+C]] to copy %%*this*%%");
+//! assert_eq!(html, r#"<p>Press <kbd class="key">Ctrl+C</kbd> to copy <mark><strong>this</strong></mark></p>"#);
+//! ```
+//!
+//! # Blocks within list items
+//!
+//! Once enabled by [`Textile::set_list_blocks`], the blocks indented
+//! with spaces or tabs right after a list belong to its last item, and
+//! may be any Textile blocks: paragraphs, code, tables or other lists.
+//! The list may continue after them. An indented block starting with
+//! an HTML tag is left after the list as raw HTML, along with
+//! the indented blocks following it.
+//!
+//! ```rust
+//! use rustextile::Textile;
+//! let html = Textile::default().set_list_blocks(true).parse(
+//!     "* Install:\n\n  bc. cargo add rustextile\n\n* Enjoy");
+//! assert_eq!(
+//!     html,
+//!     concat!("<ul>\n\t<li>Install:\n<pre><code>cargo add rustextile</code></pre>\n</li>",
+//!             "\n\t<li>Enjoy</li>\n</ul>"));
+//! ```
+//!
+//! # Containers
+//!
+//! Once enabled by [`Textile::set_containers`], the `div.`, `section.`,
+//! `aside.` and `figure.` blocks wrap their paragraphs into the respective
+//! elements, taking the usual attributes.
+//! Like blockquotes, they may be extended (`div..`) to hold several
+//! paragraphs, lists or tables, up to the next block signature.
+//!
+//! ```rust
+//! use rustextile::Textile;
+//! let textile = Textile::default().set_containers(true);
+//! let html = textile.parse("aside(tip).. Note:\n\n* Be nice\n\np. Done");
+//! assert_eq!(
+//!     html,
+//!     concat!("<aside class=\"tip\">\n\t<p>Note:</p>\n\n<ul>\n\t<li>Be nice</li>\n</ul>",
+//!             "\n</aside>\n\n<p>Done</p>"));
+//! ```
+//!
+//! # Figures
+//!
+//! Once enabled by [`Textile::set_figures`], a `fig.` block starting
+//! with an image puts it into a `<figure>`, captioned by the rest of
+//! the block, which may contain the usual inline markup. The image's
+//! alignment becomes the figure's class.
+//! The figures may also be [numbered](Textile::set_figure_numbering).
+//!
+//! ```rust
+//! use rustextile::Textile;
+//! let html = Textile::default().set_figures(true).parse("fig(photo). !<cat.jpg! The _cat_");
+//! assert_eq!(
+//!     html,
+//!     concat!("<figure class=\"photo align-left\">\n\t<img alt=\"\" src=\"cat.jpg\" />",
+//!             "\n\t<figcaption>The <em>cat</em></figcaption>\n</figure>"));
+//! ```
+//!
+//! # Video and audio
+//!
+//! In HTML5, the image syntax pointing at a video or an audio file, as told
+//! by the extension, such as `!clip.mp4(Title)!`, makes a `<video>` or
+//! an `<audio>` element with the browser's controls, labeled by the title.
+//! The link part of a video, as in `!clip.mp4!:poster.jpg`, gives
+//! its poster. Once enabled by [`Textile::set_media`], the `video.` and
+//! `audio.` blocks take one or more sources to choose from, an optional
+//! title in parentheses and, for a video, the poster in place of
+//! a blockquote's citation.
+//!
+//! ```rust
+//! use rustextile::Textile;
+//! let textile = Textile::default().set_media(true);
+//! let html = textile.parse("video.:poster.jpg clip.webm clip.mp4 (Demo)");
+//! assert_eq!(
+//!     html,
+//!     concat!("<video aria-label=\"Demo\" controls=\"controls\" poster=\"poster.jpg\">",
+//!             "<source src=\"clip.webm\" type=\"video/webm\" />",
+//!             "<source src=\"clip.mp4\" type=\"video/mp4\" /></video>"));
+//! ```
+//!
+//! # Collapsible blocks
+//!
+//! Once enabled by [`Textile::set_details`], a `details.` block becomes
+//! a `<details>` element, whose `<summary>` is either the block's first
+//! line, or the cite-like argument following the signature, as in
+//! `details.:Log`. Just like a blockquote, the extended `details..` block
+//! may hold several paragraphs.
+//!
+//! ```rust
+//! use rustextile::Textile;
+//! let textile = Textile::default().set_details(true);
+//! let html = textile.parse("details(faq). How to *install*?\nRun @cargo add@.");
+//! assert_eq!(
+//!     html,
+//!     concat!("<details class=\"faq\">\n\t<summary>How to <strong>install</strong>?</summary>",
+//!             "\n\t<p>Run <code>cargo add</code>.</p>\n</details>"));
+//! ```
+//!
+//! # CSV and TSV tables
+//!
+//! Once enabled by [`Textile::set_data_tables`], spreadsheet data can be
+//! pasted as is into a `csv.` or `tsv.` block, to become the same table
+//! as an equivalent `|a|b|` Textile table would be. The values may be
+//! quoted, as in CSV files, and contain the usual inline markup, though
+//! not the lists. Along with the table's attributes, the block's style part may
+//! give the `delimiter` (a character, or one of `comma`, `semicolon`, `tab`,
+//! `pipe` and `space`) and the `header` flag, turning the first row
+//! into the table's heading. The extended blocks (`csv..`) continue
+//! up to the next block signature, allowing the quoted values to contain
+//! blank lines.
+//!
+//! ```rust
+//! use rustextile::Textile;
+//! let html = Textile::default().set_data_tables(true).parse(
+//!     "csv(prices){delimiter: semicolon; header}. Item;Price\n*Tea*;\"1,5\"");
+//! assert_eq!(
+//!     html,
+//!     concat!("\t<table class=\"prices\">\n\t<thead>\n\t\t<tr>\n\t\t\t<th>Item</th>",
+//!             "\n\t\t\t<th>Price</th>\n\t\t</tr>\n\t</thead>\n\t<tbody>\n\t\t<tr>",
+//!             "\n\t\t\t<td><strong>Tea</strong></td>\n\t\t\t<td>1,5</td>",
+//!             "\n\t\t</tr>\n\t</tbody>\n\t</table>"));
+//! ```
 
 mod regextra;
 mod htmltools;
+mod highlight;
+mod math;
+mod emoji;
+mod entities;
+mod document;
+mod glyphs;
 mod charcounter;
 mod block;
 mod parser;
 mod html;
 mod table;
+mod tasks;
+mod source;
+mod media;
+mod responsive;
+mod frontmatter;
 mod urlutils;
 mod regex_snips;
 
 pub use ammonia;
 
-pub use crate::parser::{Textile, HtmlKind};
+pub use crate::parser::{Textile, HtmlKind, ImageFallback, TypographyLocale, CustomSpan, SpanRenderer};
+pub use crate::block::{BlockAttributes, CustomBlock, CustomBlockOutput};
+pub use crate::highlight::CodeHighlighter;
+pub use crate::math::MathRenderer;
+pub use crate::emoji::CustomEmoji;
+pub use crate::entities::{Entity, EntityKind, EntityResolver};
+pub use crate::document::Document;
+pub use crate::glyphs::{GlyphRule, GlyphSet};
+pub use crate::table::{
+    Table, TableCaption, ColumnGroup, TableSection, TableSectionKind, TableRow, TableCell,
+    Alignment, VerticalAlignment, TableDiagnostic, TableProblem, TableSummary, TableDescription,
+    CellScope,
+};
+pub use crate::tasks::Task;
+pub use crate::responsive::ResponsiveImages;
+pub use crate::frontmatter::{FrontMatter, FrontMatterValue};
+#[cfg(feature = "syntect")]
+pub use crate::highlight::SyntectHighlighter;
@@ -0,0 +1,39 @@
+//! Support for math formulas, written either inline (`$E = mc^2$`)
+//! or as a `math.` block.
+
+/// A hook that turns a formula (usually written in TeX) into HTML,
+/// for instance MathML.
+///
+/// The `display` flag tells whether the formula comes from a `math.` block
+/// (`true`) or is a part of a paragraph (`false`). As with
+/// [`CodeHighlighter`](crate::CodeHighlighter), the received formula is raw,
+/// not HTML-encoded, even in [restricted](crate::Textile::set_restricted)
+/// mode, and the returned HTML is inserted as is. Returning `None` makes
+/// the parser fall back to the default output, the one suitable for
+/// client-side renderers like KaTeX or MathJax.
+///
+/// Any closure with the matching signature works as a renderer:
+///
+/// ```rust
+/// use rustextile::Textile;
+/// let textile = Textile::default()
+///     .set_math_renderer(|tex: &str, _display: bool| {
+///         (tex == "x").then(|| "<math><mi>x</mi></math>".to_owned())
+///     });
+/// let html = textile.parse("Let $x$ be $y$");
+/// assert_eq!(
+///     html,
+///     concat!(r#"<p>Let <span class="math"><math><mi>x</mi></math></span> "#,
+///             r#"be <span class="math">\(y\)</span></p>"#));
+/// ```
+pub trait MathRenderer {
+    fn render(&self, tex: &str, display: bool) -> Option<String>;
+}
+
+impl<F> MathRenderer for F
+    where F: Fn(&str, bool) -> Option<String>
+{
+    fn render(&self, tex: &str, display: bool) -> Option<String> {
+        self(tex, display)
+    }
+}
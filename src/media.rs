@@ -0,0 +1,79 @@
+//! Video and audio files, embedded with the image syntax or
+//! the `video.` and `audio.` blocks.
+
+use lazy_static::lazy_static;
+use fancy_regex::Regex;
+
+use crate::regextra::fregex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MediaKind {
+    Video,
+    Audio,
+}
+
+impl MediaKind {
+    pub(crate) fn tag(self) -> &'static str {
+        match self {
+            Self::Video => "video",
+            Self::Audio => "audio",
+        }
+    }
+}
+
+/// Guesses the kind and the MIME type of the media file
+/// by the extension in its URL.
+pub(crate) fn media_type(url: &str) -> Option<(MediaKind, &'static str)> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let file_name = path.rsplit('/').next().unwrap_or_default();
+    let (_, extension) = file_name.rsplit_once('.')?;
+    let media_type = match extension.to_ascii_lowercase().as_str() {
+        "mp4" | "m4v" => (MediaKind::Video, "video/mp4"),
+        "webm" => (MediaKind::Video, "video/webm"),
+        "ogv" => (MediaKind::Video, "video/ogg"),
+        "mov" => (MediaKind::Video, "video/quicktime"),
+        "mp3" => (MediaKind::Audio, "audio/mpeg"),
+        "m4a" => (MediaKind::Audio, "audio/mp4"),
+        "aac" => (MediaKind::Audio, "audio/aac"),
+        "oga" | "ogg" | "opus" => (MediaKind::Audio, "audio/ogg"),
+        "wav" => (MediaKind::Audio, "audio/wav"),
+        "flac" => (MediaKind::Audio, "audio/flac"),
+        _ => return None,
+    };
+    Some(media_type)
+}
+
+/// Splits the content of a `video.` or `audio.` block into the sources
+/// and the optional title in parentheses, following them.
+pub(crate) fn split_media_block(content: &str) -> (Vec<&str>, Option<&str>) {
+    lazy_static! {
+        static ref MEDIA_RE: Regex = fregex!(
+            r"(?s)^(?P<sources>.*?)\s*(?:\((?P<title>[^)]+)\))?\s*$");
+    }
+    match MEDIA_RE.captures(content) {
+        Ok(Some(cap)) => (
+            cap.name("sources").map(|m| m.as_str().split_whitespace().collect()).unwrap_or_default(),
+            cap.name("title").map(|m| m.as_str()),
+        ),
+        _ => (Vec::new(), None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{media_type, split_media_block, MediaKind};
+
+    #[test]
+    fn test_media_type() {
+        assert_eq!(media_type("clip.MP4"), Some((MediaKind::Video, "video/mp4")));
+        assert_eq!(media_type("/a.b/song.mp3?t=10#x"), Some((MediaKind::Audio, "audio/mpeg")));
+        assert_eq!(media_type("http://example.com/mp4"), None);
+        assert_eq!(media_type("cat.jpg"), None);
+    }
+
+    #[test]
+    fn test_split_media_block() {
+        assert_eq!(split_media_block("a.webm\n a.mp4 (The demo) "), (vec!["a.webm", "a.mp4"], Some("The demo")));
+        assert_eq!(split_media_block("song.mp3"), (vec!["song.mp3"], None));
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::borrow::Cow;
 use std::io::Read;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -11,22 +11,59 @@ use fancy_regex::{Regex, Captures, Replacer, Match};
 
 use crate::charcounter::CharCounter;
 use crate::regextra::{split_with_capture, fregex, multi_replace, multi_replace_with_one, unwrap_or_empty};
-use crate::htmltools::{generate_tag, encode_html, join_html_attributes, unescape, has_raw_text, reverse_encode_html};
-use crate::table::{process_table, TABLE_SPAN_RE_S};
-use crate::urlutils::{UrlBits, UrlString};
-use crate::block::{Block, BlockAttributes, BlockHtmlAttributes};
+use crate::htmltools::{
+    generate_tag, encode_html, join_html_attributes, unescape, unescape_numeric, has_raw_text,
+    reverse_encode_html,
+};
+use crate::table::{process_table, Table, TableDiagnostic, TableSummary, TABLE_SPAN_RE_S};
+use crate::urlutils::{DataUrl, UrlBits, UrlString};
+use crate::block::{
+    Block, BlockAttributes, BlockHtmlAttributes, CustomBlock, CustomBlockOutput, CONTAINER_BLOCK_TAGS,
+};
+use crate::highlight::CodeHighlighter;
+use crate::math::MathRenderer;
+use crate::emoji::CustomEmoji;
+use crate::entities::{Entity, EntityKind, EntityResolver};
+use crate::document::Document;
+use crate::tasks::Task;
+use crate::source::SourceLines;
+use crate::media::{media_type, split_media_block, MediaKind};
+use crate::responsive::ResponsiveImages;
+use crate::frontmatter::{split_front_matter, FrontMatter};
+use crate::glyphs::{GlyphRule, GlyphSet};
 use crate::regex_snips::{
     CLS_RE_S, ALIGN_RE_S, SNIP_ACR, SNIP_ABR, SNIP_SPACE, SNIP_DIGIT,
-    SNIP_WRD, SNIP_CUR, SNIP_CHAR, LONE_AMP_RE, PNCT_RE_S, DIVIDER_RE};
+    SNIP_WRD, SNIP_CUR, SNIP_CHAR, LONE_AMP_RE, PNCT_RE_S, DIVIDER_RE,
+    CODE_LANG_RE};
 
 const SYMS_RE_S: &str = "¤§µ¶†‡•∗∴◊♠♣♥♦";
 // https://www.unicode.org/reports/tr44/#GC_Values_Table
 const BLOCK_TAGS_RE_S: &str = r"bq|bc|notextile|pre|h[1-6]|fn\d+|p|###";
 const BLOCK_TAGS_LITE_RE_S: &str = "bq|bc|p";
+// Extended forms of these blocks are handed over to `Block::new` in one piece
+const WHOLE_EXTENDED_BLOCK_TAGS: [&str; 5] = ["bc", "pre", "math", "csv", "tsv"];
 const RESTRICTED_URL_SCHEMES: [&str; 4] = ["http", "https", "ftp", "mailto"];
 const UNRESTRICTED_URL_SCHEMES: [&str; 9] = ["http", "https", "ftp", "mailto", "file", "tel", "callto", "sftp", "data"];
+// The only types of data allowed in `data:` URLs; SVG may carry scripts
+const DATA_URL_TYPES: [&str; 7] = [
+    "image/png", "image/jpeg", "image/gif", "image/webp", "image/avif", "image/bmp", "image/x-icon"];
+const DEFAULT_MAX_DATA_URL_SIZE: usize = 64 * 1024;
+
+fn textile_block_re(block_tags_pattern: &str) -> Regex {
+    fregex!(
+        &format!(
+            concat!(r"(?s)^(?P<tag>{0})(?P<atts>{1}{2}{1})\.(?P<ext>\.?)",
+                    r"(?::(?P<cite>\S+))? (?P<graf>.*)$"),
+            block_tags_pattern, *ALIGN_RE_S, *CLS_RE_S))
+}
 
 fn span_re(tag: &str) -> Regex {
+    span_re_with(tag, tag)
+}
+
+/// Same as `span_re`, but for the spans which open and close differently,
+/// like `[[kbd:Ctrl]]`.
+fn span_re_with(open_tag: &str, close_tag: &str) -> Regex {
     const PNCT: &str = r#".,"'?!;:‹›«»„“”‚‘’"#;
     fregex!(
         &format!(
@@ -35,12 +72,12 @@ fn span_re(tag: &str) -> Regex {
                 r"(?P<tag>{tag})(?!{tag})",
                 r"(?P<atts>{cls})",
                 r"(?!{tag})",
-                r"(?::(?P<cite>\S+[^{tag}]{space}))?",
-                r"(?P<content>[^{space}{tag}]+|\S.*?[^\s{tag}\n])",
+                r"(?::(?P<cite>\S+[^{ctag}]{space}))?",
+                r"(?P<content>[^{space}{ctag}]+|\S.*?[^\s{ctag}\n])",
                 r"(?P<end>[{pnct}]*)",
-                r"{tag}",
+                r"{ctag}",
                 r"(?P<tail>$|[\[\]}}<]|(?=[{pnct}]{{1,2}}[^0-9]|\s|\)))"),
-            tag=tag, cls=*CLS_RE_S, pnct=PNCT, space=SNIP_SPACE))
+            tag=open_tag, ctag=close_tag, cls=*CLS_RE_S, pnct=PNCT, space=SNIP_SPACE))
 }
 
 fn do_special<'t, R>(text: &'t str, start: &str, end: &str, method: R) -> Cow<'t, str>
@@ -79,12 +116,37 @@ fn get_image_size(url: &str) -> Option<(i64, i64)> {
     None
 }
 
-fn make_glyph_replacers(is_html5: bool) -> [(Regex, &'static str); 22] {
+type GlyphReplacer = (GlyphRule, Regex, String);
+
+/// Makes the glyph replacements for the given kind of HTML and the locale.
+/// With `utf8` the glyphs are inserted as characters, instead of entities.
+fn make_glyph_replacers(is_html5: bool, locale: TypographyLocale, utf8: bool) -> Vec<GlyphReplacer> {
+    // Which rule each of the replacers below belongs to
+    const RULES: [GlyphRule; 22] = {
+        use GlyphRule::*;
+        [
+            DimensionSign, Apostrophes, Apostrophes,
+            SingleQuotes, SingleQuotes, SingleQuotes,
+            DoubleQuotes, DoubleQuotes, DoubleQuotes,
+            Ellipsis, Ampersand, EmDash, EnDash,
+            Trademark, Registered, Copyright,
+            Fractions, Fractions, Fractions,
+            Degrees, PlusMinus, Acronyms,
+        ]
+    };
     lazy_static! {
         static ref CUR: String = format!(
             r"(?:[{0}]{1}*)?", SNIP_CUR, SNIP_SPACE);
     }
-    [
+    let (double_open, double_close) = locale.double_quotes();
+    let (single_open, single_close) = locale.single_quotes();
+    let em_dash = match locale {
+        TypographyLocale::English => r"$1&#8212;$2",
+        // A spaced en dash is the usual "Gedankenstrich"
+        TypographyLocale::German => r" &#8211; ",
+        TypographyLocale::French => r"&#160;&#8212; ",
+    };
+    let replacers: [(Regex, &str); 22] = [
         // dimension sign
         (fregex!(
             &format!(
@@ -99,26 +161,26 @@ fn make_glyph_replacers(is_html5: bool) -> [(Regex, &'static str); 22] {
         (fregex!(&format!(r"({0})'(\d+{1}?)\b(?![.]?[{1}]*?')", SNIP_SPACE, SNIP_WRD)),
          r"$1&#8217;$2"),
         // single opening following an open bracket.
-        (fregex!(r"([(\[{])'(?=\S)"), r"$1&#8216;"),
+        (fregex!(r"([(\[{])'(?=\S)"), &format!("$1{0}", single_open)),
         // single closing
         (fregex!(&format!(r"(\S)'(?={0}|{1}|<|$)", SNIP_SPACE, PNCT_RE_S)),
-         r"$1&#8217;"),
+         &format!("$1{0}", single_close)),
         // single opening
-        (fregex!(r"'"), r"&#8216;"),
+        (fregex!(r"'"), single_open),
         // double opening following an open bracket. Allows things like
         // Hello ["(Mum) & dad"]
-        (fregex!(r#"([(\[{])"(?=\S)"#), r"$1&#8220;"),
+        (fregex!(r#"([(\[{])"(?=\S)"#), &format!("$1{0}", double_open)),
         // double closing
         (fregex!(&format!(r#"(\S)"(?={0}|{1}|<|$)"#, SNIP_SPACE, PNCT_RE_S)),
-         r"$1&#8221;"),
+         &format!("$1{0}", double_close)),
         // double opening
-        (fregex!(r#"""#), r"&#8220;"),
+        (fregex!(r#"""#), double_open),
         // ellipsis
         (fregex!(r"([^.]?)\.{3}"), r"$1&#8230;"),
         // ampersand
         (fregex!(r"(\s?)&(\s)"), r"$1&amp;$2"),
         // em dash
-        (fregex!(r"(\s?)--(\s?)"), r"$1&#8212;$2"),
+        (fregex!(r"(\s?)--(\s?)"), em_dash),
         // en dash
         (fregex!(r" - "), r" &#8211; "),
         // trademark
@@ -143,7 +205,33 @@ fn make_glyph_replacers(is_html5: bool) -> [(Regex, &'static str); 22] {
         // 3+ uppercase acronym
         (fregex!(&format!(r"\b([{0}][{1}]{{2,}})\b(?:[(]([^)]*)[)])", SNIP_ABR, SNIP_ACR)),
          if is_html5 {r#"<abbr title="$2">$1</abbr>"#} else {r#"<acronym title="$2">$1</acronym>"#}),
-    ]
+    ];
+    let mut replacers: Vec<_> = RULES
+        .into_iter()
+        .zip(replacers)
+        .map(|(rule, (pattern, replacement))| (rule, pattern, replacement.to_owned()))
+        .collect();
+    if locale == TypographyLocale::French {
+        let spacing = [
+            // A typed space before ; : ! ? becomes a non-breaking one
+            // (a regular one before the colon, a narrow one elsewhere)
+            (fregex!(r" :(?=\s|$)"), "&#160;:"),
+            (fregex!(r" ([;!?])(?=\s|$)"), "&#8239;$1"),
+            // and is added where it's missing (but not before ";",
+            // which could be ending an HTML entity)
+            (fregex!(r"(?<=\w):(?=\s|$)"), "&#160;:"),
+            (fregex!(r"(?<=\w)([!?]+)(?=\s|$)"), "&#8239;$1"),
+        ];
+        replacers.extend(
+            spacing.into_iter().map(
+                |(pattern, replacement)| (GlyphRule::PunctuationSpacing, pattern, replacement.to_owned())));
+    }
+    if utf8 {
+        for replacer in replacers.iter_mut() {
+            replacer.2 = unescape_numeric(&replacer.2).into_owned();
+        }
+    }
+    replacers
 }
 
 #[derive(Clone, Debug)]
@@ -179,6 +267,60 @@ fn make_url_readable(url: &str) -> &str {
     url
 }
 
+fn eq_ignore_ascii_case(a: &str, b: &str) -> bool {
+    if a.len() == b.len() {
+        a.chars().zip(b.chars()).all(|(a_c, b_c)| a_c.eq_ignore_ascii_case(&b_c))
+    } else {
+        false
+    }
+}
+
+// Performs an equivalent of
+// `replace_all`with a regular expression
+// r"(?i)(.+)(?!(?<=</dd>|</dt>|</li>|<br/>)|(?<=<br>)|(?<=<br />))\n(?![\s|])"
+// and the replacement "$1\n".
+// This is done to avoid panic about BacktrackLimitExceeded
+// within fancy_regex::Regex::replace_all, without the need to increase
+// the limit via fancy_regex::RegexBuilder::backtrack_limit.
+fn insert_brs<'c>(text: &'c str, br: &str) -> Cow<'c, str> {
+    let num_newlines = text.match_indices('\n').count();
+    if num_newlines == 0 {
+        return text.into()
+    }
+    // The <br> is not appropriate to insert after the following prefixes
+    const STOP_PREFIXES: [&str; 6] = ["</dd>", "</dt>", "</li>", "<br/>", "<br>", "<br />"];
+    let mut output = String::with_capacity(text.len() + num_newlines * br.len());
+    let lc_text = text.to_lowercase();
+    let mut next_start = 0;
+    while let Some(rel_newline_pos) = lc_text[next_start..].find('\n') {
+        let abs_newline_pos = next_start + rel_newline_pos;
+        output += &text[next_start..abs_newline_pos];
+        // Make sure the following characters do not make <br> inappropriate
+        let is_next_good = !lc_text[abs_newline_pos + 1..]
+            .starts_with(|c| char::is_whitespace(c) || c == '|');
+        if is_next_good {
+            // The preceding sequence should also be appropriate for <br>
+            let is_prefix_good = !STOP_PREFIXES.iter().any(|p| {
+                let prefix_start = abs_newline_pos - p.len().min(abs_newline_pos);
+                let prefix = &lc_text[prefix_start..abs_newline_pos];
+                eq_ignore_ascii_case(prefix, p)
+            });
+            if is_prefix_good {
+                output += br;
+            }
+        }
+        output.push('\n');
+        next_start = abs_newline_pos + 1;
+    }
+    if output.is_empty() {
+        text.into()
+    } else {
+        output += &text[next_start..];
+        output.into()
+    }
+}
+
+
 pub(crate) struct ParserState<'t> {
     pub notes: BTreeMap<String, NoteInfo>,
     pub footnotes: IndexMap<String, String>,
@@ -193,11 +335,29 @@ pub(crate) struct ParserState<'t> {
     ol_starts: IndexMap<String, usize>,
     unreferenced_notes: BTreeMap<String, NoteInfo>,
     notelist_cache: IndexMap<String, String>,
+    entities: Vec<Entity>,
+    pub tables: Vec<Table>,
+    pub table_diagnostics: Vec<TableDiagnostic>,
+    pub(crate) source_lines: SourceLines<'t>,
+    /// The number of the line the content of the current block starts at
+    pub(crate) block_line: usize,
+    /// The tasks found in the current block, yet to be rendered
+    pub(crate) pending_tasks: VecDeque<Task>,
+    pub tasks: Vec<Task>,
+    pub figure_count: usize,
+    /// Whether the images being rendered are within the text of a link
+    in_link_text: bool,
+    pub(crate) front_matter: FrontMatter,
+    /// The blocks nested within the list items, with the numbers
+    /// of their first lines
+    nested_blocks: Vec<(String, usize)>,
 }
 
 
 impl <'t> ParserState<'t> {
-    fn new(textile: &'t Textile) -> Self {
+    /// Makes the state for parsing the `source`, which starts
+    /// at the `source_offset` of the whole text.
+    fn new(textile: &'t Textile, source: &'t str, source_offset: usize) -> Self {
         Self {
             textile,
             notes: Default::default(),
@@ -212,6 +372,17 @@ impl <'t> ParserState<'t> {
             ref_cache: Default::default(),
             notelist_cache: Default::default(),
             unreferenced_notes: Default::default(),
+            entities: Default::default(),
+            tables: Default::default(),
+            table_diagnostics: Default::default(),
+            source_lines: SourceLines::new(source, source_offset),
+            block_line: 0,
+            pending_tasks: Default::default(),
+            tasks: Default::default(),
+            figure_count: 0,
+            in_link_text: false,
+            front_matter: Default::default(),
+            nested_blocks: Default::default(),
         }
     }
 
@@ -248,7 +419,7 @@ impl <'t> ParserState<'t> {
         // Ignores subs
         if self.notes.contains_key(label) {
             let note_content = self.graf(content).into_owned();
-            if let Some(mut note) = self.notes.get_mut(label) {
+            if let Some(note) = self.notes.get_mut(label) {
                 if note.link.is_none() {
                     note.link = if link.is_empty() { None } else { Some(link.into()) };
                     note.attrs = Some(
@@ -446,17 +617,92 @@ impl <'t> ParserState<'t> {
 
     pub fn no_textile(&mut self, text: &str) -> String {
         let step1 = do_special(text, "<notextile>", "</notextile>", |cap: &Captures| {Self::f_textile(self, cap)});
+        // A custom span may have taken over the "==" marker
+        if self.textile.custom_spans.iter().any(|s| s.open == "==") {
+            return step1.into_owned();
+        }
         let step2 = do_special(&step1, "==", "==", |cap: &Captures| {Self::f_textile(self, cap)});
         step2.into_owned()
     }
 
+    /// Passes the code through the [`CodeHighlighter`] if there is one
+    /// and the language is acceptable.
+    pub(crate) fn highlight_code(&self, lang: &str, code: &str) -> Option<String> {
+        match self.textile.code_highlighter {
+            Some(ref highlighter) if CODE_LANG_RE.is_match(lang).unwrap_or_default() => {
+                let code = if self.textile.restricted {
+                    reverse_encode_html(code)
+                } else {
+                    code.into()
+                };
+                highlighter.highlight(lang, &code)
+            },
+            _ => None,
+        }
+    }
+
+    /// Renders a formula through the [`MathRenderer`] if there is one,
+    /// or wraps it into the delimiters recognized by KaTeX and MathJax.
+    pub(crate) fn render_math(&self, tex: &str, display: bool) -> String {
+        let tex = if self.textile.restricted {
+            reverse_encode_html(tex)
+        } else {
+            tex.into()
+        };
+        let rendered = self.textile.math_renderer
+            .as_ref()
+            .and_then(|renderer| renderer.render(&tex, display));
+        rendered.unwrap_or_else(|| {
+            let (open, close) = if display {(r"\[", r"\]")} else {(r"\(", r"\)")};
+            format!("{0}{1}{2}", open, encode_html(&tex, false, false), close)
+        })
+    }
+
+    /// Shelves the inline formulas, like `$E = mc^2$`, so nothing else
+    /// would touch their content.
+    fn math<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
+        lazy_static! {
+            // Just like in Pandoc, the opening "$" must be followed by
+            // a non-space character, and the closing one must be preceded
+            // by it and not followed by a digit, so "$5 and $10" is not math.
+            static ref INLINE_MATH_RE: Regex = fregex!(
+                concat!(
+                    r#"(?:^|(?<=[\s(\[{>"';]))"#,
+                    r"\$(?=[^\s$])",
+                    r"(?P<tex>(?:\\.|[^\\$])+?)",
+                    r"(?<=\S)\$",
+                    r"(?![\w$])"));
+        }
+        INLINE_MATH_RE.replace_all(text, |cap: &Captures| {
+            let formula = self.render_math(&cap["tex"], false);
+            self.shelve(format!("<span class=\"math\">{0}</span>", formula))
+        })
+    }
+
     pub fn code(&mut self, text: &str) -> String {
         fn f_code(parser: &mut ParserState, cap: &Captures) -> String {
+            lazy_static! {
+                static ref LANG_CODE_RE: Regex = fregex!(r"(?s)^\[([^\]\s]+)\](.+)$");
+            }
             let (before, text) = (&cap[1], &cap[2]);
             let after = unwrap_or_empty(cap.get(3));
             let (before, after) = get_special_options(before, after);
-            let text = encode_html(text, false, false);
-            String::from(before) + &parser.shelve(format!("<code>{0}</code>", text)) + after
+            // The brackets are only taken for the language if the highlighter
+            // accepts it, as they may well be a part of the code itself
+            let highlighted = match LANG_CODE_RE.captures(text) {
+                Ok(Some(ref m)) if CODE_LANG_RE.is_match(&m[1]).unwrap_or_default() => {
+                    let lang = &m[1];
+                    parser.highlight_code(lang, &m[2]).map(|code| {
+                        let lang_attrs = [("class".to_owned(), lang.to_owned())];
+                        generate_tag("code", Some(&code), &lang_attrs)
+                    })
+                },
+                _ => None,
+            };
+            let code_tag = highlighted.unwrap_or_else(|| {
+                format!("<code>{0}</code>", encode_html(text, false, false))
+            });
+            String::from(before) + &parser.shelve(code_tag) + after
         }
 
         fn f_pre(parser: &mut ParserState, cap: &Captures) -> String {
@@ -469,10 +715,88 @@ impl <'t> ParserState<'t> {
         }
 
         let text = do_special(text, "<code>", "</code>", |cap: &Captures| f_code(self, cap));
-        let text = do_special(&text, "@", "@", |cap: &Captures| f_code(self, cap));
+        let text = do_special(&text, "@", "@", |cap: &Captures| {
+            // "@alice and @bob" are mentions rather than code
+            if self.starts_with_mention(&cap[2]) {
+                cap[0].to_owned()
+            } else {
+                f_code(self, cap)
+            }
+        });
         do_special(&text, "<pre>", "</pre>", |cap: &Captures| f_pre(self, cap)).into_owned()
     }
 
+    fn resolve_entity(&self, kind: EntityKind, name: &str) -> Option<String> {
+        self.textile.entity_resolver
+            .as_ref()
+            .and_then(|resolver| resolver.resolve(kind, name))
+    }
+
+    /// Whether the text, which follows some `@`, begins with a known mention
+    fn starts_with_mention(&self, text: &str) -> bool {
+        lazy_static! {
+            static ref LEADING_NAME_RE: Regex = fregex!(
+                r"(?s)^(\w(?:[\w.-]*\w)?)(?![\w.-]*\w)(?=.)");
+        }
+        if self.textile.entity_resolver.is_none() {
+            return false;
+        }
+        match LEADING_NAME_RE.captures(text) {
+            Ok(Some(cap)) => self.resolve_entity(EntityKind::Mention, &cap[1]).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Turns the mentions and hashtags known to the [`EntityResolver`]
+    /// into links, skipping the ones within HTML tags and links.
+    fn entities<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
+        lazy_static! {
+            static ref SPLITTER_RE: Regex = fregex!(r"(<[\w\/!?].*?>)");
+            static ref LINK_TAG_RE: Regex = fregex!(r"(?i)^<(/?)a[\s>]");
+            static ref ENTITY_RE: Regex = fregex!(
+                r"(?:^|(?<=[\s(]))(?P<sigil>[@#])(?P<name>\w(?:[\w.-]*\w)?)(?![\w@#])");
+        }
+        if self.textile.entity_resolver.is_none() {
+            return text.into();
+        }
+        let mut result = String::with_capacity(text.len());
+        let mut within_link = false;
+        for (i, chunk) in split_with_capture(&SPLITTER_RE, text).enumerate() {
+            if i % 2 == 1 {
+                if let Ok(Some(cap)) = LINK_TAG_RE.captures(chunk) {
+                    within_link = cap[1].is_empty();
+                }
+                result.push_str(chunk);
+            } else if within_link {
+                result.push_str(chunk);
+            } else {
+                let replaced = ENTITY_RE.replace_all(chunk, |cap: &Captures| {
+                    let kind = if &cap["sigil"] == "@" {EntityKind::Mention} else {EntityKind::Hashtag};
+                    let name = &cap["name"];
+                    match self.resolve_entity(kind, name) {
+                        Some(url) => {
+                            let mut atts = vec![
+                                ("class".to_owned(),
+                                 if kind == EntityKind::Mention {"mention"} else {"hashtag"}.to_owned()),
+                                ("href".to_owned(), url.clone()),
+                            ];
+                            if let Some(ref rel) = self.textile.rel {
+                                atts.push(("rel".to_owned(), rel.clone()));
+                            }
+                            if !self.entities.iter().any(|e| e.kind == kind && e.name == name) {
+                                self.entities.push(Entity { kind, name: name.to_owned(), url });
+                            }
+                            self.shelve(generate_tag("a", Some(&cap[0]), &atts))
+                        },
+                        None => cap[0].to_owned(),
+                    }
+                });
+                result.push_str(&replaced);
+            }
+        }
+        result.into()
+    }
+
     fn get_html_comments<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
         // Search the string for HTML comments, e.g. <!-- comment text -->
         do_special(text, "<!--", "-->", |cap: &Captures| -> String {
@@ -534,89 +858,263 @@ impl <'t> ParserState<'t> {
                 &format!(
                     concat!(
                         r"(?:[\[{{])?",               // pre
-                        r"\!",                        // opening !
-                        r"([<>=]|&lt;|&gt;)?",        // optional alignment atts
-                        r"({0})",                     // optional style,class atts
-                        r"(?:\.\s)?",                 // optional dot-space
-                        r"([^\s(!]+)",                // presume this is the src
-                        r"\s?",                       // optional space
-                        r"(?:\(([^\)]+)\))?",         // optional title
-                        r"\!",                        // closing
-                        r"(?::(\S+)(?<![\]).,]))?",   // optional href
+                        r"{0}",
                         r"(?:[\]}}]|(?=[.,\s)|]|$))", // lookahead: space or end of string
                     ),
-                    *CLS_RE_S));
+                    *IMAGE_RE_S));
         }
         let f_image = |cap: &Captures| -> String {
-            let url = &cap[3];
-            if !self.is_valid_url(url) {
-                return cap[0].to_owned();
+            match self.image_html(cap, true) {
+                Some(out) => self.shelve(out),
+                None => cap[0].to_owned(),
             }
-            let mut atts = if let Some(attributes) = cap.get(2) {
-                BlockAttributes::parse(attributes.as_str(), None, true, self.textile.restricted).html_attrs()
-            } else {
-                BlockHtmlAttributes::default()
-            };
+        };
+        PATTERN.replace_all(text, f_image)
+    }
 
+    /// Renders the image matched by `IMAGE_RE_S`, unless its URL is not
+    /// allowed, applying the alignment to it if `aligned`.
+    fn image_html(&mut self, cap: &Captures, aligned: bool) -> Option<String> {
+        let url = &cap[3];
+        let mut atts = if let Some(attributes) = cap.get(2) {
+            BlockAttributes::parse(attributes.as_str(), None, true, self.textile.restricted).html_attrs()
+        } else {
+            BlockHtmlAttributes::default()
+        };
 
-            if let Some(align) = cap.get(1) {
-                let alignment = match align.as_str() {
-                    "<" | "&lt;" => "left",
-                             "=" => "center",
-                    ">" | "&gt;" => "right",
-                    _ => unreachable!("Not allowed by regex")
-                };
-                let use_align_class = match self.textile.align_class_enabled {
-                    Some(v) => v,
-                    None => match self.textile.html_type {
-                        HtmlKind::XHTML => false,
-                        HtmlKind::HTML5 => true,
-                    }
-                };
-                if use_align_class {
-                    atts.insert_css_class(format!("align-{}", alignment));
-                } else {
-                    atts.insert("align", alignment.to_owned());
+        if let Some(alignment) = cap.get(1).filter(|_| aligned).map(image_alignment) {
+            let use_align_class = match self.textile.align_class_enabled {
+                Some(v) => v,
+                None => match self.textile.html_type {
+                    HtmlKind::XHTML => false,
+                    HtmlKind::HTML5 => true,
                 }
+            };
+            if use_align_class {
+                atts.insert_css_class(format!("align-{}", alignment));
+            } else {
+                atts.insert("align", alignment.to_owned());
+            }
+        }
+
+        let optional_title = cap.get(4).map(|m| m.as_str());
+        if !self.is_allowed_image_url(url) {
+            return self.image_fallback(url, optional_title, cap.get(5).map(|m| m.as_str()));
+        }
+        if matches!(self.textile.html_type, HtmlKind::HTML5) {
+            if let Some((kind, _)) = media_type(url) {
+                // A video can't be a link, so the link gives its poster
+                let poster = cap.get(5).filter(|_| kind == MediaKind::Video).map(|m| m.as_str());
+                return self.media_html(kind, &[url], optional_title, poster, atts);
+            }
+        }
+        atts.insert("alt", optional_title.unwrap_or_default().to_owned());
+        if let Some(title) = optional_title {
+            atts.insert("title", title.to_owned());
+        }
+        self.responsive_image_attrs(url, &mut atts);
+        let img = self.image_tag(url, atts)?;
+        let out = if let Some(href) = cap.get(5) {
+            let shelved_href = self.shelve_url(
+                self.unrestrict_url(href.as_str()).into());
+            if !shelved_href.is_empty() {
+                generate_tag(
+                    "a",
+                    Some(&img),
+                    &[("href".into(), shelved_href)])
+            } else {
+                img
             }
+        } else {
+            img
+        };
+        Some(out)
+    }
 
-            let optional_title = cap.get(4).map(|m| m.as_str());
-            atts.insert("alt", optional_title.unwrap_or_default().to_owned());
+    /// Whether the image at the URL may be embedded, as far as
+    /// [`Textile::set_image_hosts`] is concerned.
+    fn is_allowed_image_url(&self, url: &str) -> bool {
+        let hosts = match self.textile.image_hosts {
+            Some(ref hosts) => hosts,
+            None => return true,
+        };
+        let url = self.unrestrict_url(url);
+        match UrlBits::parse(&url).host() {
+            None => true,
+            Some(host) => hosts.iter().any(|allowed| match allowed.strip_prefix("*.") {
+                Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+                None => host == allowed,
+            }),
+        }
+    }
 
-            if !UrlBits::parse(url).is_relative() && self.textile.get_sizes {
-                if let Some((width, height)) = get_image_size(url) {
-                    atts.insert("height", height.to_string());
-                    atts.insert("width", width.to_string());
-                }
-            };
-            let url_id = self.shelve_url(
-                self.unrestrict_url(url).into());
-            atts.insert("src", url_id);
+    /// Renders an image from a host that isn't allowed as either a link
+    /// to it or a placeholder, according to [`Textile::set_image_fallback`].
+    /// Within the text of a link, it's always a placeholder.
+    fn image_fallback(&mut self, url: &str, title: Option<&str>, href: Option<&str>) -> Option<String> {
+        if !self.is_valid_url(url) {
+            return None;
+        }
+        let text = match title {
+            Some(title) => encode_html(&self.unrestrict_url(title), true, false),
+            None => encode_html(make_url_readable(&self.unrestrict_url(url)), true, false),
+        };
+        let as_link = matches!(self.textile.image_fallback, ImageFallback::Link);
+        let content = if as_link && !self.in_link_text {
+            text
+        } else {
+            generate_tag("span", Some(&text), &[("class".into(), "image-placeholder".into())])
+        };
+        let target = if self.in_link_text {
+            None
+        } else if as_link {
+            Some(href.unwrap_or(url))
+        } else {
+            href
+        };
+        let out = match target {
+            Some(target) => {
+                let shelved_href = self.shelve_url(self.unrestrict_url(target).into());
+                generate_tag("a", Some(&content), &[("href".into(), shelved_href)])
+            },
+            None => content,
+        };
+        Some(out)
+    }
+
+    /// Adds the `srcset` and the other attributes of a responsive image,
+    /// if enabled, unless the image is embedded into its `data:` URL.
+    fn responsive_image_attrs(&mut self, url: &str, atts: &mut BlockHtmlAttributes) {
+        let textile = self.textile;
+        let images = match textile.responsive_images {
+            Some(ref images) => images,
+            None => return,
+        };
+        if !self.is_valid_url(url) || UrlBits::parse(url).scheme() == "data" {
+            return;
+        }
+        let url = self.unrestrict_url(url).into_owned();
+        let srcset: Vec<_> = images.widths.iter()
+            .map(|&width| {
+                let copy_url = self.shelve_url(images.resize(&url, width).into());
+                format!("{0} {1}w", copy_url, width)
+            })
+            .collect();
+        atts.insert("srcset", srcset.join(", "));
+        if let Some(ref sizes) = images.sizes {
+            atts.insert("sizes", sizes.clone());
+        }
+        if images.lazy {
+            atts.insert("loading", "lazy".to_owned());
+            atts.insert("decoding", "async".to_owned());
+        }
+    }
 
-            if let Some(title) = optional_title {
-                atts.insert("title", title.to_owned());
+    /// Renders a `<video>` or an `<audio>` element, which plays the first
+    /// of the `sources` supported by the browser. The sources from
+    /// disallowed hosts are left out, while an invalid URL or the one
+    /// of an unknown media type leaves out the whole element.
+    pub(crate) fn media_html(
+        &mut self,
+        kind: MediaKind,
+        sources: &[&str],
+        title: Option<&str>,
+        poster: Option<&str>,
+        mut atts: BlockHtmlAttributes
+    ) -> Option<String> {
+        if !self.is_media_sources(sources) {
+            return None;
+        }
+        let mut source_tags = String::new();
+        for &url in sources {
+            if !self.is_allowed_image_url(url) {
+                continue;
+            }
+            let mut source_atts = BlockHtmlAttributes::default();
+            source_atts.insert("src", self.shelve_url(self.unrestrict_url(url).into()));
+            if let Some((_, mime_type)) = media_type(url) {
+                source_atts.insert("type", mime_type.to_owned());
             }
+            source_tags.push_str(&generate_tag("source", None, &source_atts));
+        }
+        atts.insert("controls", "controls".to_owned());
+        if let Some(title) = title {
+            atts.insert("aria-label", title.to_owned());
+        }
+        if let Some(poster) = poster.filter(|url| self.is_valid_url(url) && self.is_allowed_image_url(url)) {
+            atts.insert("poster", self.shelve_url(self.unrestrict_url(poster).into()));
+        }
+        Some(generate_tag(kind.tag(), Some(&source_tags), &atts))
+    }
 
-            let img = generate_tag("img", None, &atts);
-            let out = if let Some(href) = cap.get(5) {
-                let shelved_href = self.shelve_url(
-                    self.unrestrict_url(href.as_str()).into());
-                if !shelved_href.is_empty() {
-                    generate_tag(
-                        "a",
-                        Some(&img),
-                        &[("href".into(), shelved_href)])
-                } else {
-                    img
-                }
-            } else {
-                img
-            };
-            self.shelve(out)
+    /// Tells whether all of the URLs point at the media files with a known
+    /// extension, and at least one of them is from an allowed host,
+    /// so they could be embedded.
+    fn is_media_sources(&self, sources: &[&str]) -> bool {
+        sources.iter().all(|url| self.is_valid_url(url) && media_type(url).is_some())
+            && sources.iter().any(|url| self.is_allowed_image_url(url))
+    }
+
+    /// Splits the content of a `fig.` block into the rendered image
+    /// it starts with, the image's alignment and the caption.
+    pub(crate) fn figure_image(
+        &mut self,
+        content: &str
+    ) -> Option<(String, Option<&'static str>, String)> {
+        lazy_static! {
+            static ref PATTERN: Regex = fregex!(
+                &format!(r"(?s)^{0}(?:\s+(?P<caption>.*))?$", *IMAGE_RE_S));
+        }
+        if self.textile.noimage {
+            return None;
+        }
+        let cap = PATTERN.captures(content).ok()??;
+        let img = self.image_html(&cap, false)?;
+        let caption = cap.name("caption").map(|m| m.as_str().trim()).unwrap_or_default().to_owned();
+        Some((self.shelve(img), cap.get(1).map(image_alignment), caption))
+    }
+
+
+    /// Makes an `<img>` tag for the given URL, unless the URL is not allowed.
+    /// The attributes other than `src` and the size are up to the caller.
+    fn image_tag(&mut self, url: &str, mut atts: BlockHtmlAttributes) -> Option<String> {
+        if !self.is_valid_url(url) || !self.is_allowed_image_url(url) {
+            return None;
+        }
+        if !UrlBits::parse(url).is_relative() && self.textile.get_sizes {
+            if let Some((width, height)) = get_image_size(url) {
+                atts.insert("height", height.to_string());
+                atts.insert("width", width.to_string());
+            }
         };
-        PATTERN.replace_all(text, f_image)
+        let url_id = self.shelve_url(
+            self.unrestrict_url(url).into());
+        atts.insert("src", url_id);
+        Some(generate_tag("img", None, &atts))
     }
 
+    /// Replaces the emoji shortcodes like `:tada:` with the emoji themselves,
+    /// or with their images for some of the custom ones.
+    fn emoji<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
+        lazy_static! {
+            static ref SHORTCODE_RE: Regex = fregex!(r"(?<![\w/]):([a-zA-Z0-9_+-]+):(?![\w/])");
+        }
+        let textile = self.textile;
+        SHORTCODE_RE.replace_all(text, |cap: &Captures| {
+            let shortcode = &cap[1];
+            let replacement = match textile.custom_emoji.get(shortcode) {
+                Some(CustomEmoji::Text(emoji)) => Some(encode_html(emoji, true, false)),
+                Some(CustomEmoji::Image(url)) => {
+                    let mut atts = BlockHtmlAttributes::default();
+                    atts.insert("alt", cap[0].to_owned());
+                    atts.insert("class", "emoji".to_owned());
+                    self.image_tag(url, atts).map(|img| self.shelve(img))
+                },
+                None => emojis::get_by_shortcode(shortcode).map(|e| e.as_str().to_owned()),
+            };
+            replacement.unwrap_or_else(|| cap[0].to_owned())
+        })
+    }
 
     fn links(&mut self, text: &str) -> String {
         let marked_text = self.mark_start_of_links(text);
@@ -752,14 +1250,34 @@ impl <'t> ParserState<'t> {
                     s=*TABLE_SPAN_RE_S,
                     a=*ALIGN_RE_S,
                     c=*CLS_RE_S));
+            // Also accepts a last row missing its trailing "|"
+            static ref NORMALIZING_PATTERN: Regex = fregex!(
+                &format!(
+                    concat!(
+                        r"(?ms)^(?:table(?P<tatts>_?{s}{a}{c})\.",
+                        r"(?P<summary>.*?)\n)?^(?P<rows>{a}{c}\.? ?\|.*\|(?:\n\|[^\n]*)?)",
+                        r"[\s]*\n\n"),
+                    s=*TABLE_SPAN_RE_S,
+                    a=*ALIGN_RE_S,
+                    c=*CLS_RE_S));
         }
         let text = format!("{0}\n\n", text);
-        match PATTERN.captures(&text) {
-            Ok(Some(cap)) => process_table(
-                self,
-                unwrap_or_empty(cap.name("tatts")),
-                &cap["rows"],
-                cap.name("summary").map(|m| m.as_str())).into(),
+        let pattern: &Regex = if self.textile.normalize_tables {
+            &NORMALIZING_PATTERN
+        } else {
+            &PATTERN
+        };
+        match pattern.captures(&text) {
+            Ok(Some(cap)) => {
+                let rows = cap.name("rows").unwrap();
+                let rows_line = self.block_line + text[..rows.start()].matches('\n').count();
+                process_table(
+                    self,
+                    unwrap_or_empty(cap.name("tatts")),
+                    rows.as_str(),
+                    rows_line,
+                    cap.name("summary").map(|m| m.as_str())).into()
+            },
             _ => text.into()
         }
     }
@@ -842,6 +1360,51 @@ impl <'t> ParserState<'t> {
         PATTERN.replace_all(text, f_rc_list)
     }
 
+    /// Turns the content of a list item starting with a checkbox,
+    /// like `[x] Done`, into a task, if the checkbox is found in the source.
+    fn task_checkbox(&mut self, content: &str) -> Option<String> {
+        lazy_static! {
+            static ref CHECKBOX_RE: Regex = fregex!(r"^\[[ xX]\](?=\s|$)");
+        }
+        if !self.textile.task_lists || !CHECKBOX_RE.is_match(content).unwrap_or_default() {
+            return None;
+        }
+        let checked = content.as_bytes()[1] != b' ';
+        if self.pending_tasks.front()?.checked != checked {
+            return None;
+        }
+        let task = self.pending_tasks.pop_front()?;
+        let mut attrs = vec![("type".to_owned(), "checkbox".to_owned())];
+        if task.checked {
+            attrs.push(("checked".to_owned(), "checked".to_owned()));
+        }
+        if self.textile.interactive_tasks {
+            attrs.push(("data-task".to_owned(), self.tasks.len().to_string()));
+        } else {
+            attrs.push(("disabled".to_owned(), "disabled".to_owned()));
+        }
+        self.tasks.push(task);
+        let checkbox = self.shelve(generate_tag("input", None, &attrs));
+        Some(checkbox + &content[3..])
+    }
+
+    /// Renders the blocks nested within a list item, which are marked
+    /// at the end of its content.
+    fn nested_blocks(&mut self, content: &str) -> Option<String> {
+        let (content, index) = content
+            .strip_suffix(":nested")?
+            .rsplit_once(self.textile.uid.as_str())?;
+        let (text, first_line) = std::mem::take(
+            self.nested_blocks.get_mut(index.parse::<usize>().ok()?)?);
+        // The nested lists are separate from the one containing them
+        let pending_tasks = std::mem::take(&mut self.pending_tasks);
+        let ol_starts = std::mem::take(&mut self.ol_starts);
+        let html = self.block(&text, first_line);
+        self.pending_tasks = pending_tasks;
+        self.ol_starts = ol_starts;
+        Some(content.trim_end().to_owned() + &self.shelve(format!("\n{0}\n", html)))
+    }
+
     pub(crate) fn textile_lists<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
         lazy_static! {
             static ref PATTERN: Regex = fregex!(
@@ -917,6 +1480,10 @@ impl <'t> ParserState<'t> {
                 } else {
                     "li"
                 };
+                let task_content = if litem == "li" { self.task_checkbox(content) } else { None };
+                let content = task_content.as_deref().unwrap_or(content);
+                let nested_content = self.nested_blocks(content);
+                let content = nested_content.as_deref().unwrap_or(content);
                 let next = list_items.get(index + 1);
                 let show_item = !content.is_empty();
 
@@ -956,17 +1523,17 @@ impl <'t> ParserState<'t> {
                     if show_item {
                         format!(
                             "{0}<{1}{2}>\n{0}\t<{3}>{4}",
-                            tabs, ltype, atts.to_string(),
+                            tabs, ltype, atts,
                             litem, content)
                     } else {
                         format!(
                             "{0}<{1}{2}>",
-                            tabs, ltype, atts.to_string())
+                            tabs, ltype, atts)
                     }
                 } else if show_item {
                     format!(
                         "{0}\t<{1}{2}>{3}",
-                        tabs, litem, atts.to_string(), content)
+                        tabs, litem, atts, content)
                 } else {
                     String::new()
                 };
@@ -1002,59 +1569,6 @@ impl <'t> ParserState<'t> {
     /// Inserts <br> before each newline within a specified HTML tag,
     /// unless it is inappropriate (like when a <br> is already there).
     pub(crate) fn do_tag_br<'a>(&mut self, tag: &'static str, input: &'a str) -> Cow<'a, str> {
-
-        fn eq_ignore_ascii_case(a: &str, b: &str) -> bool {
-            if a.len() == b.len() {
-                a.chars().zip(b.chars()).all(|(a_c, b_c)| a_c.eq_ignore_ascii_case(&b_c))
-            } else {
-                false
-            }
-        }
-        // Performs an equivalent of
-        // `replace_all`with a regular expression
-        // r"(?i)(.+)(?!(?<=</dd>|</dt>|</li>|<br/>)|(?<=<br>)|(?<=<br />))\n(?![\s|])"
-        // and the replacement "$1\n".
-        // This is done to avoid panic about BacktrackLimitExceeded
-        // within fancy_regex::Regex::replace_all, without the need to increase
-        // the limit via fancy_regex::RegexBuilder::backtrack_limit.
-        fn insert_brs<'c>(text: &'c str, br: &str) -> Cow<'c, str> {
-            let num_newlines = text.match_indices('\n').count();
-            if num_newlines == 0 {
-                return text.into()
-            }
-            // The <br> is not appropriate to insert after the following prefixes
-            const STOP_PREFIXES: [&str; 6] = ["</dd>", "</dt>", "</li>", "<br/>", "<br>", "<br />"];
-            let mut output = String::with_capacity(text.len() + num_newlines * br.len());
-            let lc_text = text.to_lowercase();
-            let mut next_start = 0;
-            while let Some(rel_newline_pos) = lc_text[next_start..].find('\n') {
-                let abs_newline_pos = next_start + rel_newline_pos;
-                output += &text[next_start..abs_newline_pos];
-                // Make sure the following characters do not make <br> inappropriate
-                let is_next_good = !lc_text[abs_newline_pos + 1..]
-                    .starts_with(|c| char::is_whitespace(c) || c == '|');
-                if is_next_good {
-                    // The preceding sequence should also be appropriate for <br>
-                    let is_prefix_good = !STOP_PREFIXES.iter().any(|p| {
-                        let prefix_start = abs_newline_pos - p.len().min(abs_newline_pos);
-                        let prefix = &lc_text[prefix_start..abs_newline_pos];
-                        eq_ignore_ascii_case(prefix, *p)
-                    });
-                    if is_prefix_good {
-                        output += br;
-                    }
-                }
-                output.push('\n');
-                next_start = abs_newline_pos + 1;
-            }
-            if output.is_empty() {
-                text.into()
-            } else {
-                output += &text[next_start..];
-                output.into()
-            }
-        }
-
         let mut regex_cache = self.textile.regex_cache.borrow_mut();
         let pattern = regex_cache
             .entry(line!())
@@ -1072,6 +1586,11 @@ impl <'t> ParserState<'t> {
         })
     }
 
+    /// Same as `do_tag_br`, but for the content of a tag given on its own.
+    pub(crate) fn do_br<'a>(&self, content: &'a str) -> Cow<'a, str> {
+        insert_brs(content, self.textile.proper_br_tag())
+    }
+
     fn do_p_br<'a>(&mut self, input: &'a str) -> Cow<'a, str> {
         lazy_static! {
             static ref TAG_RE: Regex = fregex!(r"(?s)<(p|h[1-6])([^>]*?)>(.*)(</\1>)");
@@ -1218,17 +1737,36 @@ impl <'t> ParserState<'t> {
     /// regexes.  For all remaining passes, we use glyph_search
     fn glyphs<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
         lazy_static! {
-            static ref HTML5_GLYPH_REPLACERS: [(Regex, &'static str); 22] = make_glyph_replacers(true);
-            static ref XHTML_GLYPH_REPLACERS: [(Regex, &'static str); 22] = make_glyph_replacers(false);
+            static ref GLYPH_REPLACERS: HashMap<(bool, TypographyLocale, bool), Vec<GlyphReplacer>> = {
+                let mut replacers = HashMap::new();
+                for locale in [TypographyLocale::English, TypographyLocale::German, TypographyLocale::French] {
+                    for is_html5 in [false, true] {
+                        for utf8 in [false, true] {
+                            replacers.insert(
+                                (is_html5, locale, utf8),
+                                make_glyph_replacers(is_html5, locale, utf8));
+                        }
+                    }
+                }
+                replacers
+            };
             static ref SPLITTER_RE: Regex = fregex!(r"(<[\w\/!?].*?>)");
         }
 
         let text = text.trim_end_matches('\n');
         let mut result = Vec::new();
 
-        let replacers = match self.textile.html_type {
-            HtmlKind::HTML5 => &HTML5_GLYPH_REPLACERS[..],
-            HtmlKind::XHTML => &XHTML_GLYPH_REPLACERS[..],
+        let is_html5 = match self.textile.html_type {
+            HtmlKind::HTML5 => true,
+            HtmlKind::XHTML => false,
+        };
+        let replacers = &GLYPH_REPLACERS[
+            &(is_html5, self.textile.typography_locale, self.textile.utf8_glyphs)];
+        let glyph_set = &self.textile.glyph_set;
+        let caps_replacers = if glyph_set.is_enabled(GlyphRule::Caps) {
+            &self.textile.dyn_glyph_replacers[..]
+        } else {
+            &[]
         };
         // split the text by any angle-bracketed tags
         for (i, raw_line) in split_with_capture(&SPLITTER_RE, text).enumerate() {
@@ -1242,14 +1780,25 @@ impl <'t> ParserState<'t> {
                     } else {
                         Cow::Borrowed(raw_line)
                     };
-                    multi_replace(
+                    // The shortcodes go first, so the glyph rules, like
+                    // the French spacing before a colon, wouldn't break them
+                    let raw_line = if self.textile.emoji {
+                        Cow::Owned(self.emoji(&raw_line).into_owned())
+                    } else {
+                        raw_line
+                    };
+                    let line = multi_replace(
                         raw_line,
                         replacers
                             .iter()
-                            .map(|item| (&item.0, item.1))
-                            .chain(self.textile.dyn_glyph_replacers.iter()
+                            .filter(|item| glyph_set.is_enabled(item.0))
+                            .map(|item| (&item.1, item.2.as_str()))
+                            .chain(caps_replacers.iter()
                                    .map(|item| (&item.0, item.1.as_str())))
-                    ).into()
+                            .chain(glyph_set.custom_rules().iter()
+                                   .map(|item| (&item.0, item.1.as_str())))
+                    );
+                    line.into()
                 } else {
                     Cow::Borrowed(raw_line)
                 });
@@ -1439,7 +1988,8 @@ impl <'t> ParserState<'t> {
                 &UNRESTRICTED_URL_SCHEMES[..]
             };
             let scheme_in_list = allowed_schemes.contains(&(uri_parts.scheme()));
-            let is_valid_url = uri_parts.scheme().is_empty() || scheme_in_list;
+            let is_valid_url = uri_parts.scheme().is_empty()
+                || scheme_in_list && (uri_parts.scheme() != "data" || self.is_valid_data_url(&url));
             if !is_valid_url {
                 return in_.replace(&format!("{0}linkStartMarker:", self.textile.uid), "");
             }
@@ -1460,7 +2010,10 @@ impl <'t> ParserState<'t> {
             let title = encode_html(title, false, false);
 
             let text = if !self.textile.noimage {
-                self.image(text)
+                self.in_link_text = true;
+                let text = self.image(text);
+                self.in_link_text = false;
+                text
             } else {
                 Cow::Borrowed(text)
             };
@@ -1509,14 +2062,25 @@ impl <'t> ParserState<'t> {
                 &UNRESTRICTED_URL_SCHEMES[..]
             };
             allowed_schemes.contains(&(uri_parts.scheme()))
+                && (uri_parts.scheme() != "data" || self.is_valid_data_url(url))
         }
     }
 
+    /// Whether a `data:` URL holds an image of one of the allowed types
+    /// within [the size limit](Textile::set_max_data_url_size).
+    fn is_valid_data_url(&self, url: &str) -> bool {
+        DataUrl::parse(url).is_some_and(|data_url| {
+            DATA_URL_TYPES.contains(&data_url.mime_type.as_str())
+                && data_url.size <= self.textile.max_data_url_size
+        })
+    }
+
     pub fn graf<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
         let lite = self.textile.lite;
         let text = Cow::Borrowed(text);
         let text = if !lite {self.no_textile(&text).into()} else {text};
         let text = if !lite {self.code(&text).into()} else {text};
+        let text = if self.textile.math {self.math(&text)} else {text};
         let text = self.get_html_comments(&text);
         let text = self.get_refs(&text);
         let ltext = self.glyph_quoted_quote(&text);
@@ -1525,11 +2089,18 @@ impl <'t> ParserState<'t> {
         let text = if !lite {self.table(&text)} else {text};
         let text = if !lite {self.redcloth_list(&text)} else {text};
         let text = if !lite { self.textile_lists(&text)} else {text };
-        let text = self.span(&text);
+        let text = self.phrases(&text);
+        Cow::Owned(text.trim_end_matches('\n').to_owned())
+    }
+
+    /// The last stages of `graf`: the spans, the entities, the footnote
+    /// and note references, and the glyphs.
+    pub(crate) fn phrases(&mut self, text: &str) -> String {
+        let text = self.span(text);
+        let text = self.entities(&text);
         let text = self.footnote_ref(&text);
         let text = self.note_ref(&text);
-        let text = self.glyphs(&text);
-        Cow::Owned(text.trim_end_matches('\n').to_owned())
+        self.glyphs(&text).into_owned()
     }
 
     fn span<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
@@ -1539,52 +2110,111 @@ impl <'t> ParserState<'t> {
                 span_re(r"\-"), span_re(r"__"), span_re(r"_"), span_re(r"%"),
                 span_re(r"\+"), span_re(r"~"), span_re(r"\^")
             ];
+            static ref EXTRA_TAG_PATTERNS: [Regex; 14] = [
+                span_re(r"\*\*"), span_re(r"\*"), span_re(r"\?\?"),
+                span_re(r"\-"), span_re(r"__"), span_re(r"_"), span_re(r"%%"), span_re(r"%"),
+                span_re(r"\+"), span_re(r"~~"), span_re(r"~"), span_re(r"\^"),
+                span_re_with(r"\[\[", r"\]\]"), span_re(r"''"),
+            ];
         }
         self.span_depth += 1;
         let can_replace = self.span_depth <= self.textile.max_span_depth;
 
-        let f_span = |cap: &Captures| -> String {
-            // pre, tag, atts, cite, content, end, tail = match.groups()
-            let tag = match &cap[2] {
-                "*" => "strong",
-                "**" =>"b",
-                "??" =>"cite",
-                "_" => "em",
-                "__" =>"i",
-                "-" => "del",
-                "%" => "span",
-                "+" => "ins",
-                "~" => "sub",
-                "^" => "sup",
-                _ => unreachable!("Not allowed by the regex")
-            };
-            let atts = &cap[3];
-            let mut html_atts = BlockAttributes::parse(atts, None, true, self.textile.restricted).html_attrs();
-            if let Some(cite) = cap.get(4) {
-                html_atts.insert("cite", cite.as_str().trim().to_owned());
-            }
-            let content = &cap[5];
-            let content = self.span(content);
-            let end = &cap[6];
-            let (pre, tail) = get_special_options(
-                unwrap_or_empty(cap.get(1)),
-                unwrap_or_empty(cap.get(7)));
-            let mut open_tag = String::from("<") + tag;
-            join_html_attributes(&mut open_tag, &html_atts);
-            open_tag.push('>');
-            let close_tag = format!("</{}>", tag);
-            let (open_tag_id, close_tag_id) = self.store_tags(open_tag, close_tag);
-            String::from(pre) + &open_tag_id + &content + end + &close_tag_id + tail
-        };
-
         let mut text = Cow::Borrowed(text);
         if can_replace {
-            text = Cow::Owned(multi_replace_with_one(text, TAG_PATTERNS.iter(), f_span));
+            let textile = self.textile;
+            for custom_span in textile.custom_spans.iter() {
+                let replaced = custom_span.pattern
+                    .replace_all(&text, |cap: &Captures| self.f_span(cap, Some(&custom_span.renderer)))
+                    .into_owned();
+                text = Cow::Owned(replaced);
+            }
+            let patterns = if textile.extra_spans {
+                EXTRA_TAG_PATTERNS.iter()
+            } else {
+                TAG_PATTERNS.iter()
+            };
+            text = Cow::Owned(
+                multi_replace_with_one(
+                    text,
+                    patterns,
+                    |cap: &Captures| self.f_span(cap, None)));
         }
         self.span_depth -= 1;
         text
     }
 
+    fn f_span(&mut self, cap: &Captures, renderer: Option<&SpanRenderer>) -> String {
+        // pre, tag, atts, cite, content, end, tail = match.groups()
+        let atts = &cap[3];
+        let attributes = BlockAttributes::parse(atts, None, true, self.textile.restricted);
+        let cite = cap.get(4).map(|m| m.as_str().trim());
+        let content = &cap[5];
+        let content = self.span(content);
+        let end = &cap[6];
+        let (pre, tail) = get_special_options(
+            unwrap_or_empty(cap.get(1)),
+            unwrap_or_empty(cap.get(7)));
+        let (open_tag, close_tag) = match renderer {
+            Some(SpanRenderer::Callback(callback)) => {
+                callback(&CustomSpan {
+                    marker: &cap[2],
+                    attributes: &attributes,
+                    cite,
+                    content: &content,
+                    front_matter: &self.front_matter,
+                })
+            },
+            Some(SpanRenderer::Element { name, attributes: element_atts }) => {
+                let mut html_atts = BlockHtmlAttributes::default();
+                for (key, value) in element_atts {
+                    html_atts.insert(key, value.clone());
+                }
+                for (key, value) in attributes.html_attrs().iter() {
+                    if key == "class" {
+                        for class in value.split_whitespace() {
+                            html_atts.insert_css_class(class);
+                        }
+                    } else {
+                        html_atts.insert(key, value.clone());
+                    }
+                }
+                html_atts += ("cite", cite.map(str::to_owned));
+                let mut open_tag = String::from("<") + name;
+                join_html_attributes(&mut open_tag, &html_atts);
+                open_tag.push('>');
+                (open_tag, format!("</{}>", name))
+            },
+            None => {
+                let tag = match &cap[2] {
+                    "*" => "strong",
+                    "**" =>"b",
+                    "??" =>"cite",
+                    "_" => "em",
+                    "__" =>"i",
+                    "-" => "del",
+                    "%" => "span",
+                    "+" => "ins",
+                    "~" => "sub",
+                    "^" => "sup",
+                    "%%" => "mark",
+                    "~~" => "small",
+                    "[[" => "kbd",
+                    "''" => "q",
+                    _ => unreachable!("Not allowed by the regex")
+                };
+                let mut html_atts = attributes.html_attrs();
+                html_atts += ("cite", cite.map(str::to_owned));
+                let mut open_tag = String::from("<") + tag;
+                join_html_attributes(&mut open_tag, &html_atts);
+                open_tag.push('>');
+                (open_tag, format!("</{}>", tag))
+            },
+        };
+        let (open_tag_id, close_tag_id) = self.store_tags(open_tag, close_tag);
+        String::from(pre) + &open_tag_id + &content + end + &close_tag_id + tail
+    }
+
     fn store_tags(&mut self, open_tag: String, close_tag: String) -> (String, String) {
         self.ref_index += 1;
         self.ref_cache.insert(self.ref_index, open_tag);
@@ -1596,6 +2226,74 @@ impl <'t> ParserState<'t> {
         (open_tag_id, close_tag_id)
     }
 
+    /// Restores everything taken out of the text while parsing it:
+    /// the shelved pieces, the tags of the spans and the URLs.
+    fn retrieve_all(&mut self, text: String) -> String {
+        let text = self.retrieve(text);
+        let text = text.replace(
+            &format!("{0}:glyph:", &self.textile.uid),
+            "");
+        let text = self.retrieve_tags(&text);
+        self.retrieve_urls(&text).into_owned()
+    }
+
+    /// Replaces the markers `process_table` leaves in place of the content
+    /// of the table descriptions, captions and cells with that content,
+    /// which is finalized in the tables' model as well. The tables which
+    /// didn't make it into the output are dropped, along with their
+    /// diagnostics.
+    fn retrieve_tables(&mut self, text: &str) -> String {
+        if self.tables.is_empty() {
+            return text.to_owned();
+        }
+        let mut tables = std::mem::take(&mut self.tables);
+        for content in tables.iter_mut().flat_map(Table::contents_mut) {
+            *content = self.retrieve_all(std::mem::take(content));
+        }
+        let contents: Vec<Vec<String>> = tables.iter_mut()
+            .map(|table| table.contents_mut().map(|content| content.clone()).collect())
+            .collect();
+
+        let mut regex_cache = self.textile.regex_cache.borrow_mut();
+        let pattern = regex_cache
+            .entry(line!())
+            .or_default()
+            .entry("")
+            .or_insert_with(
+                || fregex!(&format!(r"{0}(?P<table>[0-9]+)\.(?P<item>[0-9]+):cell", self.textile.uid)));
+        let mut is_present = vec![false; tables.len()];
+        let result = pattern.replace_all(text, |cap: &Captures| {
+            let table_index: usize = cap["table"].parse().unwrap_or(usize::MAX);
+            let item_index: usize = cap["item"].parse().unwrap_or(usize::MAX);
+            match contents.get(table_index).and_then(|items| items.get(item_index)) {
+                Some(content) => {
+                    is_present[table_index] = true;
+                    content.clone()
+                },
+                None => String::new(),
+            }
+        }).into_owned();
+        drop(regex_cache);
+
+        // Renumber the diagnostics of the remaining tables
+        let mut new_indices = Vec::with_capacity(is_present.len());
+        let mut next_index = 0;
+        for present in is_present.iter() {
+            new_indices.push(present.then_some(next_index));
+            next_index += usize::from(*present);
+        }
+        let diagnostics = std::mem::take(&mut self.table_diagnostics);
+        self.table_diagnostics = diagnostics.into_iter()
+            .filter_map(|d| Some(TableDiagnostic { table: new_indices[d.table]?, ..d }))
+            .collect();
+
+        self.tables = tables.into_iter()
+            .zip(is_present)
+            .filter_map(|(table, present)| present.then_some(table))
+            .collect();
+        result
+    }
+
     fn retrieve_tags(&self, text: &str) -> String {
         let f_retrieve_tags = |cap: &Captures| -> String {
             let tag_id = cap[1].parse::<u32>().expect("must be an integer");
@@ -1626,25 +2324,95 @@ impl <'t> ParserState<'t> {
         result.into_owned()
     }
 
-    pub fn block<'b>(&mut self, text: &'b str) -> String {
-        fn textile_block_re(block_tags_pattern: &str) -> Regex {
-            fregex!(
-                &format!(
-                    concat!(r"(?s)^(?P<tag>{0})(?P<atts>{1}{2}{1})\.(?P<ext>\.?)",
-                            r"(?::(?P<cite>\S+))? (?P<graf>.*)$"),
-                    block_tags_pattern, *ALIGN_RE_S, *CLS_RE_S))
+    /// Finds the tasks within a block, which starts at the line
+    /// `first_line` of the document.
+    fn locate_tasks(&self, block: &str, first_line: usize) -> VecDeque<Task> {
+        if self.textile.task_lists && block.contains('[') {
+            self.source_lines.locate_tasks(block, first_line)
+        } else {
+            VecDeque::new()
         }
+    }
+
+    /// Joins a list with the indented blocks following it, which get
+    /// nested within its last item, and with the items continuing
+    /// the list after them. The nested blocks are replaced with
+    /// a `{uid}{index}:nested` marker on a line of its own.
+    fn gather_list_blocks<'b>(
+        &mut self,
+        textblocks: &[(usize, &'b str)],
+        block_index: &mut usize
+    ) -> Cow<'b, str> {
         lazy_static! {
-            static ref TEXTILE_TAG_RE: Regex = textile_block_re(
-                BLOCK_TAGS_RE_S);
-            static ref TEXTILE_LIGHT_TAG_RE: Regex = textile_block_re(
-                BLOCK_TAGS_LITE_RE_S);
-            static ref MULTI_ENDLINE_RE: Regex = fregex!(r"(\n{2,})");
-            static ref BR_TAG_RE: Regex = fregex!(r"(?i)<br\s*?/?>");
+            static ref LIST_START_RE: Regex = fregex!(
+                &format!(r"^(?:[*;:]|#(?:_|\d+)?){0}[ .]", *CLS_RE_S));
+            static ref LIST_ITEM_RE: Regex = fregex!(
+                &format!(r"^(?:[*;:]+|[*;:#]*#(?:_|\d+)?){0}[ .]", *CLS_RE_S));
+            static ref HTML_START_RE: Regex = fregex!(r"^\s*<[a-zA-Z/!?]");
         }
-        let mut out = Vec::<Cow<'b, str>>::new();
-        let tag_pattern: &Regex = if self.textile.lite {
+        let (_, block) = textblocks[*block_index - 1];
+        if self.textile.lite || !self.textile.list_blocks
+            || !LIST_START_RE.is_match(block).unwrap_or_default()
+        {
+            return Cow::Borrowed(block);
+        }
+        // An indented piece of raw HTML stays after the list, as it always did
+        let is_indented = |b: &str| {
+            b.starts_with([' ', '\t']) && !b.trim().is_empty()
+                && !HTML_START_RE.is_match(b).unwrap_or_default()
+        };
+        let mut gathered = Cow::Borrowed(block);
+        let mut next_index = *block_index;
+        loop {
+            // The blocks separated by blank lines alternate with
+            // the blank lines themselves
+            let mut nested_end = next_index;
+            while textblocks.get(nested_end + 1).map(|(_, b)| is_indented(b)).unwrap_or_default() {
+                nested_end += 2;
+            }
+            if nested_end == next_index {
+                break;
+            }
+            let nested = &textblocks[next_index + 1..nested_end];
+            let nested_text: String = nested.iter().map(|(_, b)| *b).collect();
+            self.nested_blocks.push((dedent(&nested_text), nested[0].0));
+            let gathered = gathered.to_mut();
+            gathered.push('\n');
+            gathered.push_str(&format!(
+                "{0}{1}:nested", self.textile.uid, self.nested_blocks.len() - 1));
+            next_index = nested_end;
+
+            match textblocks.get(next_index + 1) {
+                Some(&(next_line, next)) if LIST_ITEM_RE.is_match(next).unwrap_or_default() => {
+                    gathered.push('\n');
+                    gathered.push_str(next);
+                    let tasks = self.locate_tasks(next, next_line);
+                    self.pending_tasks.extend(tasks);
+                    next_index += 2;
+                },
+                _ => break,
+            }
+        }
+        *block_index = next_index;
+        gathered
+    }
+
+    /// Processes the blocks of the text, which starts
+    /// at the line `first_line` of the document.
+    pub fn block<'b>(&mut self, text: &'b str, first_line: usize) -> String {
+        lazy_static! {
+            static ref TEXTILE_TAG_RE: Regex = textile_block_re(
+                BLOCK_TAGS_RE_S);
+            static ref TEXTILE_LIGHT_TAG_RE: Regex = textile_block_re(
+                BLOCK_TAGS_LITE_RE_S);
+            static ref MULTI_ENDLINE_RE: Regex = fregex!(r"(\n{2,})");
+            static ref BR_TAG_RE: Regex = fregex!(r"(?i)<br\s*?/?>");
+        }
+        let mut out = Vec::<Cow<'b, str>>::new();
+        let tag_pattern: &Regex = if self.textile.lite {
             &TEXTILE_LIGHT_TAG_RE
+        } else if let Some(ref custom_tag_re) = self.textile.custom_block_tag_re {
+            custom_tag_re
         } else {
             &TEXTILE_TAG_RE
         };
@@ -1656,8 +2424,19 @@ impl <'t> ParserState<'t> {
         let mut cite = None;
         let mut last_outer_closing = String::new();
         let mut eat = false;
-        let textblocks = split_with_capture(&MULTI_ENDLINE_RE, text);
-        for block in textblocks {
+        // Each of the blocks along with the number of its first line
+        let mut line = first_line;
+        let textblocks: Vec<(usize, &str)> = split_with_capture(&MULTI_ENDLINE_RE, text)
+            .map(|block| {
+                let block_line = line;
+                line += block.matches('\n').count();
+                (block_line, block)
+            })
+            .collect();
+        let outer_block_line = self.block_line;
+        let mut block_index = 0;
+        while let Some(&(block_line, block)) = textblocks.get(block_index) {
+            block_index += 1;
             if block.trim().is_empty() {
                 if !eat_whitespace {
                     whitespace += block;
@@ -1671,10 +2450,19 @@ impl <'t> ParserState<'t> {
                 cite = None;
                 eat = false;
             }
+            self.pending_tasks = self.locate_tasks(block, block_line);
+            self.block_line = block_line;
 
             eat_whitespace = false;
             let mut is_anonymous_block = true;
-            let block_output = if let Ok(Some(m)) = tag_pattern.captures(block) {
+            // A media block without the media to embed is just a paragraph
+            let signature = tag_pattern.captures(block).ok().flatten().filter(|m| {
+                let tag = unwrap_or_empty(m.get(1));
+                !matches!(tag, "video" | "audio")
+                    || self.textile.custom_blocks.contains_key(tag)
+                    || self.is_media_sources(&split_media_block(unwrap_or_empty(m.get(5))).0)
+            });
+            let block_output = if let Some(m) = signature {
                 is_anonymous_block = false;
                 // Last block was extended, so close it
                 if !ext.is_empty() {
@@ -1686,8 +2474,42 @@ impl <'t> ParserState<'t> {
                 atts = unwrap_or_empty(m.get(2));
                 ext = unwrap_or_empty(m.get(3));
                 cite = m.get(4).as_ref().map(Match::as_str);
-                let content = unwrap_or_empty(m.get(5));
-                let bdata = Block::new(tag, atts, cite, content, self);
+                let mut graf = unwrap_or_empty(m.get(5));
+                if tag == "details" && cite.is_none() && !self.textile.custom_blocks.contains_key(tag) {
+                    // The summary is the first line, unless given as the cite
+                    let (summary, rest) = graf.split_once('\n').unwrap_or((graf, ""));
+                    cite = Some(summary);
+                    graf = rest;
+                    self.block_line += 1;
+                }
+                let mut content = Cow::Borrowed(graf);
+                if !ext.is_empty() && WHOLE_EXTENDED_BLOCK_TAGS.contains(&tag)
+                    && !self.textile.custom_blocks.contains_key(tag)
+                {
+                    // Collect all the paragraphs of the extended block
+                    // up to the next block signature, so the code
+                    // (and the code highlighter) could be seen as a whole.
+                    let mut pending_whitespace = "";
+                    while let Some(&(_, next_block)) = textblocks.get(block_index) {
+                        if next_block.trim().is_empty() {
+                            pending_whitespace = next_block;
+                        } else if tag_pattern.is_match(next_block).unwrap_or_default() {
+                            break;
+                        } else {
+                            let gathered = content.to_mut();
+                            gathered.push_str(pending_whitespace);
+                            gathered.push_str(next_block);
+                            pending_whitespace = "";
+                        }
+                        block_index += 1;
+                    }
+                    if !pending_whitespace.is_empty() {
+                        // Leave the trailing whitespace to the next block
+                        block_index -= 1;
+                    }
+                    ext = "";
+                }
+                let bdata = Block::new(tag, atts, cite, &content, !ext.is_empty(), self);
                 eat = bdata.eat;
                 last_outer_closing.replace_range(.., &bdata.outer_closing);
 
@@ -1697,9 +2519,17 @@ impl <'t> ParserState<'t> {
                     + &bdata.inner_closing
                     + if ext.is_empty() { &bdata.outer_closing } else { "" }
             } else {
+                let is_textile_block = tag == "p" || tag == "bq" || CONTAINER_BLOCK_TAGS.contains(&tag)
+                    || tag.strip_prefix("fn").map(|n| n.bytes().all(|b| b.is_ascii_digit())).unwrap_or_default();
+                let block = if is_textile_block {
+                    self.gather_list_blocks(&textblocks, &mut block_index)
+                } else {
+                    Cow::Borrowed(block)
+                };
+                let block = block.as_ref();
                 let raw_block = DIVIDER_RE.is_match(block).unwrap_or_default();
                 if !ext.is_empty() || (!block.starts_with(' ') && !raw_block) {
-                    let bdata =  Block::new(tag, atts, cite, block, self);
+                    let bdata =  Block::new(tag, atts, cite, block, !ext.is_empty(), self);
                     eat = bdata.eat;
                     last_outer_closing.replace_range(.., &bdata.outer_closing);
                     // Skip outer tag because this is part of a continuing extended block
@@ -1741,6 +2571,7 @@ impl <'t> ParserState<'t> {
                 *last_output += last_outer_closing.as_str();
             }
         }
+        self.block_line = outer_block_line;
         out.join("")
     }
 
@@ -1771,6 +2602,17 @@ impl <'t> ParserState<'t> {
             }
         }
 
+        let utf8 = self.textile.utf8_glyphs;
+        let glyph = |entity: &'static str| -> Cow<'static, str> {
+            if utf8 {unescape_numeric(entity).into_owned().into()} else {entity.into()}
+        };
+        let (double_open, double_close) = self.textile.typography_locale.double_quotes();
+        let (double_open, double_close) = (glyph(double_open), glyph(double_close));
+        let (single_open, single_close) = self.textile.typography_locale.single_quotes();
+        let (single_open, single_close) = (glyph(single_open), glyph(single_close));
+        let nbsp = if utf8 {"\u{a0}"} else {"&nbsp;"};
+        let double_quotes = self.textile.glyph_set.is_enabled(GlyphRule::DoubleQuotes);
+        let single_quotes = self.textile.glyph_set.is_enabled(GlyphRule::SingleQuotes);
         let f_glyph_quoted_quote = |m: &Captures| -> String {
             // Check the correct closing character was found.
             let mut pre_char_buf = [0u8; 4];
@@ -1781,22 +2623,22 @@ impl <'t> ParserState<'t> {
                         return m[0].to_owned();
                     }
                     let new_pre = match pre_char {
-                        '"' => "&#8220;",
-                        '\'' => "&#8216;",
-                        ' ' => "&nbsp;",
+                        '"' if double_quotes => &double_open,
+                        '\'' if single_quotes => &single_open,
+                        ' ' => nbsp,
                         // a frugal replacement for char::to_string()
                         x => x.encode_utf8(&mut pre_char_buf)
                     };
                     let new_post = match post_char {
-                        '"' => "&#8221;",
-                        '\'' => "&#8217;",
-                        ' ' => "&nbsp;",
+                        '"' if double_quotes => &double_close,
+                        '\'' if single_quotes => &single_close,
+                        ' ' => nbsp,
                         x => x.encode_utf8(&mut post_char_buf)
                     };
                     let found = &m["quoted"];
                     let found: Cow<str> = if found.len() > 1 {
                         self.glyphs(found).trim_end().to_owned().into()
-                    } else if found == "\"" {
+                    } else if found == "\"" && double_quotes {
                         "&quot;".into()
                     } else {
                         found.into()
@@ -1812,6 +2654,103 @@ impl <'t> ParserState<'t> {
 }
 
 
+/// Everything known about an inline span with a custom marker, which has been
+/// registered by [`Textile::register_span`].
+#[derive(Debug)]
+pub struct CustomSpan<'a> {
+    /// The opening marker of the span, like `==` in `==text==`
+    pub marker: &'a str,
+    /// The attributes given right after the opening marker, like `(big)`
+    /// in `==(big)text==`. Their values are not HTML-escaped: that's done
+    /// once they're turned into a `String` for a tag. In the restricted
+    /// mode the classes, the id and the `{style}` part are left out.
+    pub attributes: &'a BlockAttributes,
+    /// An optional citation, like in `??(cls):source text??`
+    pub cite: Option<&'a str>,
+    /// The content of the span, with any nested spans already processed.
+    /// It still contains some internal placeholders, so it must not
+    /// be altered.
+    pub content: &'a str,
+    /// The [front matter](Textile::set_front_matter) of the document
+    pub front_matter: &'a FrontMatter,
+}
+
+type SpanCallback = dyn Fn(&CustomSpan) -> (String, String);
+
+/// How the spans with a custom marker are to be rendered.
+/// Check [`Textile::register_span`] for details.
+pub enum SpanRenderer {
+    /// Wraps the span into an element with the given name and attributes.
+    /// The attributes written in the markup, such as `==(big)text==`,
+    /// are added to them.
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    /// A function returning the opening and the closing HTML to be placed
+    /// around the span's content.
+    Callback(Box<SpanCallback>),
+}
+
+impl SpanRenderer {
+    /// A shortcut for [`SpanRenderer::Element`] without any extra attributes.
+    pub fn element(name: &str) -> Self {
+        Self::Element { name: name.to_owned(), attributes: Vec::new() }
+    }
+}
+
+pub(crate) struct CustomSpanMarker {
+    open: String,
+    pattern: Regex,
+    renderer: SpanRenderer,
+}
+
+/// The language whose typographic conventions are followed when
+/// the quotes, dashes and such are replaced with their proper glyphs.
+/// Check [`Textile::set_typography_locale`] for details.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TypographyLocale {
+    /// “Double” and ‘single’ quotes, em dashes without spaces
+    #[default]
+    English,
+    /// „Double“ and ‚single‘ quotes, spaced en dashes
+    German,
+    /// « Double » and ‹ single › quotes with narrow no-break spaces,
+    /// spaced em dashes and no-break spaces before `;`, `:`, `!` and `?`
+    French,
+}
+
+impl TypographyLocale {
+    fn double_quotes(self) -> (&'static str, &'static str) {
+        match self {
+            Self::English => ("&#8220;", "&#8221;"),
+            Self::German => ("&#8222;", "&#8220;"),
+            Self::French => ("&#171;&#8239;", "&#8239;&#187;"),
+        }
+    }
+
+    fn single_quotes(self) -> (&'static str, &'static str) {
+        match self {
+            Self::English => ("&#8216;", "&#8217;"),
+            Self::German => ("&#8218;", "&#8216;"),
+            Self::French => ("&#8249;&#8239;", "&#8239;&#8250;"),
+        }
+    }
+}
+
+/// What becomes of the images from the hosts not allowed by
+/// [`Textile::set_image_hosts`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImageFallback {
+    /// A link to the image, with its title or URL for the text, or to where
+    /// the image itself was meant to lead
+    #[default]
+    Link,
+    /// A `<span class="image-placeholder">` with the image's title or URL,
+    /// linked where the image was meant to lead
+    Placeholder,
+}
+
 /// Determines which flavor of HTML the [`Textile`] parser will produce.
 /// Check [`Textile::set_html_kind`] for details.
 pub enum HtmlKind {
@@ -1822,6 +2761,8 @@ pub enum HtmlKind {
 type AmmoniaConfigurator = dyn for <'a, 'b>
     Fn(&'a mut crate::ammonia::Builder<'b>) -> &'a crate::ammonia::Builder<'b>;
 
+type CustomBlockHandler = dyn Fn(&CustomBlock) -> CustomBlockOutput;
+
 /// The core structure responsible for converting Textile markup into HTML.
 ///
 /// Example:
@@ -1834,21 +2775,52 @@ type AmmoniaConfigurator = dyn for <'a, 'b>
 /// assert_eq!(html, "<h1>It works!</h1>");
 /// ```
 pub struct Textile {
-    uid: String,
+    pub(crate) uid: String,
     pub(crate) link_prefix: String,
     pub(crate) restricted: bool,
     pub(crate) raw_block_enabled: bool,
     pub(crate) align_class_enabled: Option<bool>,
     block_tags: bool,
     pub(crate) lite: bool,
+    pub(crate) normalize_tables: bool,
+    pub(crate) html5_tables: bool,
+    pub(crate) task_lists: bool,
+    front_matter: bool,
+    pub(crate) figure_label: Option<String>,
+    image_hosts: Option<Vec<String>>,
+    max_data_url_size: usize,
+    image_fallback: ImageFallback,
+    interactive_tasks: bool,
+    list_blocks: bool,
+    pub(crate) table_summary: TableSummary,
+    pub(crate) table_wrapper: Option<String>,
     noimage: bool,
     get_sizes: bool,
     max_span_depth: u32,
-    html_type: HtmlKind,
+    pub(crate) html_type: HtmlKind,
+    typography_locale: TypographyLocale,
+    glyph_set: GlyphSet,
+    utf8_glyphs: bool,
     rel: Option<String>,
     regex_cache: std::cell::RefCell<HashMap<u32, HashMap<&'static str, Regex>>>,
     dyn_glyph_replacers: [(Regex, String); 1],
     sanitizer_config: Option<Box<AmmoniaConfigurator>>,
+    code_highlighter: Option<Box<dyn CodeHighlighter>>,
+    responsive_images: Option<ResponsiveImages>,
+    extra_spans: bool,
+    pub(crate) math: bool,
+    pub(crate) data_tables: bool,
+    pub(crate) details: bool,
+    pub(crate) containers: bool,
+    pub(crate) figures: bool,
+    pub(crate) media: bool,
+    math_renderer: Option<Box<dyn MathRenderer>>,
+    emoji: bool,
+    custom_emoji: HashMap<String, CustomEmoji>,
+    entity_resolver: Option<Box<dyn EntityResolver>>,
+    pub(crate) custom_blocks: IndexMap<String, Box<CustomBlockHandler>>,
+    custom_block_tag_re: Option<Regex>,
+    custom_spans: Vec<CustomSpanMarker>,
 }
 
 fn normalize_newlines(text: &str) -> String {
@@ -1863,6 +2835,46 @@ fn normalize_newlines(text: &str) -> String {
         .into()
 }
 
+lazy_static! {
+    /// An image, like `!<(cls)image.png(Title)!:link`. The groups are
+    /// the alignment, the attributes, the URL, the title and the link.
+    static ref IMAGE_RE_S: String = format!(
+        concat!(
+            r"\!",                        // opening !
+            r"([<>=]|&lt;|&gt;)?",        // optional alignment atts
+            r"({0})",                     // optional style,class atts
+            r"(?:\.\s)?",                 // optional dot-space
+            r"([^\s(!]+)",                // presume this is the src
+            r"\s?",                       // optional space
+            r"(?:\(([^\)]+)\))?",         // optional title
+            r"\!",                        // closing
+            r"(?::(\S+)(?<![\]).,]))?",   // optional href
+        ),
+        *CLS_RE_S);
+}
+
+fn image_alignment(align: Match) -> &'static str {
+    match align.as_str() {
+        "<" | "&lt;" => "left",
+        "=" => "center",
+        ">" | "&gt;" => "right",
+        _ => unreachable!("Not allowed by regex")
+    }
+}
+
+/// Removes the indentation common to all the lines of the text.
+fn dedent(text: &str) -> String {
+    let indent = text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start_matches([' ', '\t']).len())
+        .min()
+        .unwrap_or_default();
+    text.split('\n')
+        .map(|line| line.get(indent..).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 fn time_based_uid() -> String {
     let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
     let mut hasher = DefaultHasher::new();
@@ -1893,12 +2905,43 @@ impl Default for Textile {
             align_class_enabled: None,
             block_tags: true,
             lite: false,
+            normalize_tables: false,
+            html5_tables: false,
+            task_lists: false,
+            front_matter: false,
+            figure_label: None,
+            image_hosts: None,
+            max_data_url_size: DEFAULT_MAX_DATA_URL_SIZE,
+            image_fallback: ImageFallback::Link,
+            interactive_tasks: false,
+            list_blocks: false,
+            table_summary: TableSummary::default(),
+            table_wrapper: None,
             noimage: false,
             get_sizes: false,
             max_span_depth: 5,
             html_type: HtmlKind::HTML5,
+            typography_locale: TypographyLocale::English,
+            glyph_set: GlyphSet::default(),
+            utf8_glyphs: false,
             rel: None,
             sanitizer_config: None,
+            code_highlighter: None,
+            responsive_images: None,
+            extra_spans: false,
+            math: false,
+            data_tables: false,
+            details: false,
+            containers: false,
+            figures: false,
+            media: false,
+            math_renderer: None,
+            emoji: false,
+            custom_emoji: HashMap::new(),
+            entity_resolver: None,
+            custom_blocks: Default::default(),
+            custom_block_tag_re: None,
+            custom_spans: Vec::new(),
             regex_cache: std::cell::RefCell::new(Default::default()),
             dyn_glyph_replacers: [
                 // 3+ uppercase
@@ -1916,23 +2959,38 @@ impl Textile {
     /// if [`set_html_kind`](Textile::set_html_kind)[`(HtmlKind::XHTML)`](HtmlKind::XHTML)
     /// was called previously).
     pub fn parse(&self, text: &str) -> String {
+        self.parse_document(text).html
+    }
+
+    /// Same as [`Textile::parse`], but besides the HTML returns whatever
+    /// else has been found in the text, like the
+    /// [mentions](Textile::set_entity_resolver).
+    pub fn parse_document(&self, text: &str) -> Document {
+        let (front_matter, source_offset) = if self.front_matter {
+            split_front_matter(text).unwrap_or_default()
+        } else {
+            Default::default()
+        };
+        let text = &text[source_offset..];
 
         if text.trim().is_empty() {
-            return text.to_owned();
+            return Document { html: text.to_owned(), front_matter, ..Default::default() };
         }
 
+        let source = text;
         let text = if self.restricted {
             Cow::Owned(encode_html(text, false, false))
         } else {
             Cow::Borrowed(text)
         };
 
-        let mut state = ParserState::new(self);
+        let mut state = ParserState::new(self, source, source_offset);
+        state.front_matter = front_matter;
         let text = normalize_newlines(&text)
             .replace(&state.textile.uid, "");
 
         let text = if self.block_tags {
-            let text = state.block(&text);
+            let text = state.block(&text, 0);
             state.place_note_lists(&text).into_owned()
         } else {
             let text = text + "\n\n";
@@ -1940,27 +2998,24 @@ impl Textile {
             let text = state.glyph_quoted_quote(&text);
             // Inline markup (em, strong, sup, sub, del etc).
             let text = state.span(&text);
+            let text = state.entities(&text);
             // Glyph level substitutions (mainly typographic -- " & ' => curly
             // quotes, -- => em-dash etc.
             state.glyphs(&text).into_owned()
         };
 
-        let text = state.retrieve(text);
-        let text = text.replace(
-            &format!("{0}:glyph:", &state.textile.uid),
-            "");
-
-        let text = state.retrieve_tags(&text);
-        let text = state.retrieve_urls(&text);
+        let text = state.retrieve_all(text);
+        let text = state.retrieve_tables(&text);
 
         let text = match self.sanitizer_config {
-            Some(ref configurator) =>
-                configurator(
-                    crate::ammonia::Builder::default().link_rel(None)
-                )
-                .clean(&text)
-                .to_string()
-                .into(),
+            Some(ref configurator) => {
+                let mut builder = crate::ammonia::Builder::default();
+                let sanitizer = configurator(builder.link_rel(None));
+                for content in state.tables.iter_mut().flat_map(Table::contents_mut) {
+                    *content = sanitizer.clean(content).to_string();
+                }
+                sanitizer.clean(&text).to_string()
+            },
             None => text,
         };
 
@@ -1970,16 +3025,27 @@ impl Textile {
             static ref BR_PATTERN: Regex = fregex!(r"<br( /)?>(?!\n)");
         }
 
-        let text = BR_PATTERN.replace_all(
-            &text,
-            match self.html_type {
-                HtmlKind::XHTML => "<br />\n",
-                HtmlKind::HTML5 => "<br>\n",
-            });
+        let br_replacement = match self.html_type {
+            HtmlKind::XHTML => "<br />\n",
+            HtmlKind::HTML5 => "<br>\n",
+        };
+        for content in state.tables.iter_mut().flat_map(Table::contents_mut) {
+            if let Cow::Owned(replaced) = BR_PATTERN.replace_all(content, br_replacement) {
+                *content = replaced;
+            }
+        }
+        let text = BR_PATTERN.replace_all(&text, br_replacement);
 
         let text = text.trim_end_matches('\n');
 
-        text.to_string()
+        Document {
+            html: text.to_string(),
+            entities: state.entities,
+            tables: state.tables,
+            table_diagnostics: state.table_diagnostics,
+            tasks: state.tasks,
+            front_matter: state.front_matter,
+        }
     }
 
     /// Enables automatic addition of `width` and `height` attributes
@@ -2010,6 +3076,54 @@ impl Textile {
         self
     }
 
+    /// Makes the typographic replacements (quotes, dashes, spacing around
+    /// punctuation) follow the conventions of the given language.
+    /// English is the default.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use rustextile::{Textile, TypographyLocale};
+    /// let textile = Textile::default().set_typography_locale(TypographyLocale::German);
+    /// let html = textile.parse(r#"Er sagte "Hallo" -- und ging."#);
+    /// assert_eq!(html, "<p>Er sagte &#8222;Hallo&#8220; &#8211; und ging.</p>");
+    ///
+    /// let textile = Textile::default().set_typography_locale(TypographyLocale::French);
+    /// let html = textile.parse(r#"Il a dit "Bonjour" : vraiment ?"#);
+    /// assert_eq!(html, "<p>Il a dit &#171;&#8239;Bonjour&#8239;&#187;&#160;: vraiment&#8239;?</p>");
+    /// ```
+    pub fn set_typography_locale(mut self, locale: TypographyLocale) -> Self {
+        self.typography_locale = locale;
+        self
+    }
+
+    /// Selects which typographic replacements (curly quotes, dashes,
+    /// `(C)` → © and so on) are made, and adds custom ones.
+    /// Check [`GlyphSet`] for details.
+    pub fn set_glyph_set(mut self, glyph_set: GlyphSet) -> Self {
+        self.glyph_set = glyph_set;
+        self
+    }
+
+    /// Makes the typographic replacements (curly quotes, dashes and so on)
+    /// come out as UTF-8 characters, rather than as numeric HTML entities
+    /// like `&#8217;`. The note backlink markers and footnote symbols
+    /// are always characters, while `<`, `>`, `&` and the quotes within
+    /// attributes stay escaped either way.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let textile = Textile::default().set_utf8_glyphs(true);
+    /// let html = textile.parse(r#"It's "4x4" -- <b>& more</b>..."#);
+    /// assert_eq!(html, "<p>It’s “4×4” — <b>&amp; more</b>…</p>");
+    /// ```
+    pub fn set_utf8_glyphs(mut self, value: bool) -> Self {
+        self.utf8_glyphs = value;
+        self
+    }
+
     /// Controls the restricted mode, which (when enabled) forces the parser to
     ///
     /// * escape any raw HTML
@@ -2024,6 +3138,283 @@ impl Textile {
         self
     }
 
+    /// Makes the grid of every table rectangular, fixing the mistakes
+    /// which would otherwise produce broken HTML:
+    ///
+    /// * the rows missing their trailing `|` are kept apart from the next rows
+    /// * the short rows are padded with empty cells
+    /// * the colspans and rowspans which don't fit into the grid are clamped,
+    ///   and the zero ones are dropped
+    ///
+    /// Each of the fixes is reported in the
+    /// [`Document::table_diagnostics`](crate::Document::table_diagnostics),
+    /// with the position of the problem.
+    ///
+    /// ```rust
+    /// use rustextile::{Textile, TableProblem};
+    /// let textile = Textile::default().set_normalize_tables(true);
+    /// let doc = textile.parse_document("|a|b|c|\n|d|\\5. e|\n|f|");
+    /// let problems: Vec<_> = doc.table_diagnostics.iter()
+    ///     .map(|d| (d.row, d.column, d.problem))
+    ///     .collect();
+    /// assert_eq!(problems, [
+    ///     (1, 1, TableProblem::ColspanOverflow { colspan: 5, clamped_to: 2 }),
+    ///     (2, 1, TableProblem::MissingCells { count: 2 }),
+    /// ]);
+    /// ```
+    pub fn set_normalize_tables(mut self, value: bool) -> Self {
+        self.normalize_tables = value;
+        self
+    }
+
+    /// Makes the tables conform to HTML5, when it's the kind of HTML
+    /// being produced (see [`Textile::set_html_kind`]):
+    ///
+    /// * the obsolete `summary` attribute, given like `table. Summary`,
+    ///   is replaced as set by [`Textile::set_table_summary`]
+    /// * the widths of the columns, given like `|:\2. 80|`, become styles
+    /// * the header cells get the `scope` attribute: `col` within
+    ///   the `thead` and the rows consisting of header cells only,
+    ///   and `row` elsewhere
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let textile = Textile::default().set_html5_tables(true);
+    /// let html = textile.parse("table. Scores\n|_. Name |_. Score |\n|_. Alice | 10 |");
+    /// assert!(html.contains("<caption>Scores</caption>"));
+    /// assert!(html.contains(r#"<th scope="col">Name </th>"#));
+    /// assert!(html.contains(r#"<th scope="row">Alice </th>"#));
+    /// ```
+    pub fn set_html5_tables(mut self, value: bool) -> Self {
+        self.html5_tables = value;
+        self
+    }
+
+    /// Selects how the summary of a table is presented when the tables
+    /// conform to HTML5 (see [`Textile::set_html5_tables`]).
+    /// It becomes the caption by default.
+    ///
+    /// ```rust
+    /// use rustextile::{Textile, TableSummary};
+    /// let textile = Textile::default()
+    ///     .set_html5_tables(true)
+    ///     .set_table_summary(TableSummary::Description)
+    ///     .set_uid("doc");
+    /// let html = textile.parse("table. Scores\n| Alice | 10 |");
+    /// assert!(html.trim_start().starts_with(concat!(
+    ///     "<p id=\"tabledescdoc-1\">Scores</p>\n",
+    ///     "\t<table aria-describedby=\"tabledescdoc-1\">")));
+    /// ```
+    pub fn set_table_summary(mut self, value: TableSummary) -> Self {
+        self.table_summary = value;
+        self
+    }
+
+    /// Wraps every table into a `div` of the given class, which can
+    /// be styled to scroll the tables too wide for the page, like
+    /// `.table-wrapper { overflow-x: auto; }`.
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let textile = Textile::default().set_table_wrapper(Some("table-wrapper"));
+    /// let html = textile.parse("| a | b |");
+    /// assert!(html.trim_start().starts_with("<div class=\"table-wrapper\">\n\t<table>"));
+    /// assert!(html.ends_with("</table>\n\t</div>"));
+    /// ```
+    pub fn set_table_wrapper(mut self, class: Option<&str>) -> Self {
+        self.table_wrapper = class.map(str::to_owned);
+        self
+    }
+
+    /// Recognizes the `csv.` and `tsv.` blocks, which turn the spreadsheet
+    /// data into tables (see [CSV and TSV tables](crate#csv-and-tsv-tables)).
+    /// They're not available in the lite mode.
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// assert_eq!(Textile::default().parse("csv. a,b"), "<p>csv. a,b</p>");
+    /// let html = Textile::default().set_data_tables(true).parse("csv. a,b");
+    /// assert!(html.contains("<td>a</td>"));
+    /// ```
+    pub fn set_data_tables(mut self, enabled: bool) -> Self {
+        self.data_tables = enabled;
+        self.update_block_tag_re();
+        self
+    }
+
+    /// Recognizes the `details.` blocks, which become the collapsible
+    /// `<details>` elements (see [Collapsible blocks](crate#collapsible-blocks)).
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// assert_eq!(Textile::default().parse("details. Why?"), "<p>details. Why?</p>");
+    /// let html = Textile::default().set_details(true).parse("details. Why?");
+    /// assert!(html.starts_with("<details>\n\t<summary>Why?</summary>"));
+    /// ```
+    pub fn set_details(mut self, enabled: bool) -> Self {
+        self.details = enabled;
+        self.update_block_tag_re();
+        self
+    }
+
+    /// Recognizes the `div.`, `section.`, `aside.` and `figure.` blocks,
+    /// which wrap their content into the respective elements
+    /// (see [Containers](crate#containers)).
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// assert_eq!(Textile::default().parse("aside. Note"), "<p>aside. Note</p>");
+    /// let html = Textile::default().set_containers(true).parse("aside. Note");
+    /// assert_eq!(html, "<aside>\n\t<p>Note</p>\n</aside>");
+    /// ```
+    pub fn set_containers(mut self, enabled: bool) -> Self {
+        self.containers = enabled;
+        self.update_block_tag_re();
+        self
+    }
+
+    /// Recognizes the `fig.` blocks, which put the images into
+    /// the captioned `<figure>` elements (see [Figures](crate#figures)).
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let text = "fig. 3 shows the results.";
+    /// assert_eq!(Textile::default().parse(text), "<p>fig. 3 shows the results.</p>");
+    /// let html = Textile::default().set_figures(true).parse("fig. !cat.jpg! The cat");
+    /// assert!(html.starts_with("<figure>"));
+    /// ```
+    pub fn set_figures(mut self, enabled: bool) -> Self {
+        self.figures = enabled;
+        self.update_block_tag_re();
+        self
+    }
+
+    /// Recognizes the `video.` and `audio.` blocks, which embed the media
+    /// files (see [Video and audio](crate#video-and-audio)). A block
+    /// with anything but the allowed URLs of such files in place of
+    /// the sources stays a paragraph.
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let textile = Textile::default().set_media(true);
+    /// assert!(textile.parse("audio. song.mp3").starts_with("<audio"));
+    /// assert_eq!(textile.parse("video. games are fun"), "<p>video. games are fun</p>");
+    /// ```
+    pub fn set_media(mut self, enabled: bool) -> Self {
+        self.media = enabled;
+        self.update_block_tag_re();
+        self
+    }
+
+    /// Recognizes the front matter, a header of `key: value` lines between
+    /// the `---` ones, which the text may start with. Instead of being
+    /// rendered, it's returned by [`Textile::parse_document`] as
+    /// [`Document::front_matter`](crate::Document::front_matter), which
+    /// is also given to the [custom blocks](CustomBlock::front_matter)
+    /// and [spans](CustomSpan::front_matter). The
+    /// [entity resolver](Textile::set_entity_resolver) and the
+    /// [code highlighter](Textile::set_code_highlighter) don't get it,
+    /// so they work the same for any document.
+    ///
+    /// Besides the single values, optionally quoted, there may be lists,
+    /// like `tags: [news, rust]`, or with the `- ` items on the following
+    /// lines, as well as the `#` comments. A header with anything else
+    /// in it isn't considered the front matter.
+    ///
+    /// ```rust
+    /// use rustextile::{Textile, CustomBlockOutput};
+    /// let textile = Textile::default()
+    ///     .set_front_matter(true)
+    ///     .register_block("byline", |block| {
+    ///         let author = block.front_matter.get("author")
+    ///             .and_then(|value| value.as_text())
+    ///             .unwrap_or("Anonymous");
+    ///         CustomBlockOutput::Html(
+    ///             format!("<p class=\"byline\">{0} by {1}</p>", block.content, author))
+    ///     });
+    /// let doc = textile.parse_document("---\nauthor: Ann\ntags: [news]\n---\nbyline. Written\n\nHello");
+    /// assert_eq!(doc.html, "<p class=\"byline\">Written by Ann</p>\n\n<p>Hello</p>");
+    /// assert_eq!(doc.front_matter["tags"].as_list(), Some(&["news".to_owned()][..]));
+    /// ```
+    pub fn set_front_matter(mut self, value: bool) -> Self {
+        self.front_matter = value;
+        self
+    }
+
+    /// Turns the list items starting with a checkbox, like `* [ ] To do`
+    /// or `* [x] Done`, into tasks, rendered with disabled checkbox inputs.
+    /// The tasks, with their positions in the source, are available through
+    /// [`Document::tasks`](crate::Document::tasks).
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let textile = Textile::default().set_task_lists(true);
+    /// let html = textile.parse("* [x] Dishes\n* [ ] Laundry");
+    /// assert_eq!(html, concat!(
+    ///     "<ul>\n",
+    ///     "\t<li><input type=\"checkbox\" checked=\"checked\" disabled=\"disabled\" /> Dishes</li>\n",
+    ///     "\t<li><input type=\"checkbox\" disabled=\"disabled\" /> Laundry</li>\n",
+    ///     "</ul>"));
+    /// ```
+    pub fn set_task_lists(mut self, value: bool) -> Self {
+        self.task_lists = value;
+        self
+    }
+
+    /// Makes the checkboxes of the tasks (see [`Textile::set_task_lists`])
+    /// enabled, with the `data-task` attribute giving the index of the task
+    /// within [`Document::tasks`](crate::Document::tasks), so a script could
+    /// toggle the task's box in the source when the checkbox is clicked.
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let textile = Textile::default().set_task_lists(true).set_interactive_tasks(true);
+    /// let html = textile.parse("* [x] Dishes\n* [ ] Laundry");
+    /// assert!(html.contains(r#"<input type="checkbox" data-task="1" /> Laundry"#));
+    /// ```
+    pub fn set_interactive_tasks(mut self, value: bool) -> Self {
+        self.interactive_tasks = value;
+        self
+    }
+
+    /// Nests the blocks indented right after a list within its last item
+    /// (see [Blocks within list items](crate#blocks-within-list-items)).
+    /// Otherwise they're left after the list as they are.
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let text = "* Install:\n\n  bc. cargo add rustextile";
+    /// assert!(Textile::default().parse(text).starts_with("<ul>\n\t<li>Install:</li>\n</ul>"));
+    /// let html = Textile::default().set_list_blocks(true).parse(text);
+    /// assert!(html.ends_with("<pre><code>cargo add rustextile</code></pre>\n</li>\n</ul>"));
+    /// ```
+    pub fn set_list_blocks(mut self, value: bool) -> Self {
+        self.list_blocks = value;
+        self
+    }
+
+    /// Numbers the figures given by the `fig.` blocks (which are enabled
+    /// by [`Textile::set_figures`]), putting the label
+    /// and the number before their captions, like "Figure 3. Caption".
+    /// A numbered figure without an `id` of its own gets one like
+    /// `figure-3`, so it could be referred to.
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let textile = Textile::default()
+    ///     .set_figures(true)
+    ///     .set_figure_numbering(Some("Fig."));
+    /// let html = textile.parse("fig. !cat.jpg! The cat\n\nfig(#dog). !dog.jpg!");
+    /// assert!(html.contains("<figcaption>Fig. 1. The cat</figcaption>"));
+    /// assert!(html.contains(r#"<figure id="figure-1">"#));
+    /// assert!(html.contains(r#"<figure id="dog">"#));
+    /// assert!(html.contains("<figcaption>Fig. 2</figcaption>"));
+    /// ```
+    pub fn set_figure_numbering(mut self, label: Option<&str>) -> Self {
+        self.figure_label = label.map(str::to_owned);
+        self
+    }
+
     /// Enables the "lite mode", which limits the set of allowed Textile
     /// blocks to paragraphs and blockquotes only.
     ///
@@ -2111,6 +3502,346 @@ impl Textile {
         self
     }
 
+    /// Sets a [`CodeHighlighter`] for the code blocks and inline code
+    /// that have their language specified, e.g. `bc[rust]. let x = 1;`
+    /// or `@[rust]let x = 1;@`.
+    ///
+    /// Check [`SyntectHighlighter`](crate::SyntectHighlighter) (behind
+    /// the `syntect` feature) for a ready to use implementation.
+    pub fn set_code_highlighter<H>(mut self, highlighter: H) -> Self
+        where H: CodeHighlighter + 'static
+    {
+        self.code_highlighter = Some(Box::new(highlighter));
+        self
+    }
+
+    /// Allows embedding the images (along with videos and audio) only
+    /// from the given hosts, so no third party could watch the readers.
+    /// A host may be given as `*.example.com` to allow all its subdomains.
+    /// The images with relative URLs are always allowed, while the others
+    /// are replaced according to [`Textile::set_image_fallback`],
+    /// including the ones within the text of links. `None`, the default,
+    /// allows any host.
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let textile = Textile::default().set_image_hosts(Some(&["cdn.example.com"]));
+    /// let html = textile.parse(
+    ///     "!https://cdn.example.com/a.png! !/b.png! !https://tracker.example.org/c.png(C)!");
+    /// assert_eq!(
+    ///     html,
+    ///     concat!(r#"<p><img alt="" src="https://cdn.example.com/a.png" /> "#,
+    ///             r#"<img alt="" src="/b.png" /> "#,
+    ///             r#"<a href="https://tracker.example.org/c.png">C</a></p>"#));
+    /// ```
+    pub fn set_image_hosts(mut self, hosts: Option<&[&str]>) -> Self {
+        self.image_hosts = hosts.map(|hosts| hosts.iter().map(|h| h.to_lowercase()).collect());
+        self
+    }
+
+    /// Sets what the images from the hosts not allowed by
+    /// [`Textile::set_image_hosts`] become.
+    ///
+    /// ```rust
+    /// use rustextile::{Textile, ImageFallback};
+    /// let textile = Textile::default()
+    ///     .set_image_hosts(Some(&[]))
+    ///     .set_image_fallback(ImageFallback::Placeholder);
+    /// let html = textile.parse("!http://example.org/cat.jpg(A cat)!");
+    /// assert_eq!(html, r#"<p><span class="image-placeholder">A cat</span></p>"#);
+    /// ```
+    pub fn set_image_fallback(mut self, fallback: ImageFallback) -> Self {
+        self.image_fallback = fallback;
+        self
+    }
+
+    /// Sets the maximum size, in bytes, of the data embedded into a `data:`
+    /// URL, once decoded, which is 64 KiB by default. Such URLs, allowed
+    /// for images and links unless in the [restricted mode](Textile::set_restricted),
+    /// must also hold a PNG, JPEG, GIF, WebP, AVIF, BMP or ICO image and be
+    /// well formed, with valid base64 data, if declared so. The others are
+    /// left as they are written, as any other disallowed URL.
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let textile = Textile::default().set_max_data_url_size(10);
+    /// let html = textile.parse(
+    ///     "!data:image/gif;base64,R0lGODlh! !data:image/gif;base64,R0lGODlhAQABAAAAACw=!");
+    /// assert_eq!(
+    ///     html,
+    ///     concat!(r#"<p><img alt="" src="data:image/gif;base64,R0lGODlh" /> "#,
+    ///             "!data:image/gif;base64,R0lGODlhAQABAAAAACw=!</p>"));
+    /// ```
+    pub fn set_max_data_url_size(mut self, size: usize) -> Self {
+        self.max_data_url_size = size;
+        self
+    }
+
+    /// Makes the images responsive, adding the `srcset` of their copies
+    /// of different widths. Check [`ResponsiveImages`] for details.
+    pub fn set_responsive_images(mut self, images: ResponsiveImages) -> Self {
+        self.responsive_images = Some(images);
+        self
+    }
+
+    /// Enables the [extra inline elements](crate#extra-inline-elements):
+    /// `[[keys]]`, `%%highlighted%%`, `~~fine print~~` and `''quotation''`.
+    /// They're off by default, since such markers may be found in
+    /// the existing texts, like `[[wiki links]]`, `~~strikethrough~~`
+    /// of Markdown or the doubled apostrophes.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let text = "Press [[Ctrl+C]] to copy %%this%%";
+    /// assert_eq!(Textile::default().parse(text), "<p>Press [[Ctrl+C]] to copy %%this%%</p>");
+    /// assert_eq!(
+    ///     Textile::default().set_extra_spans(true).parse(text),
+    ///     "<p>Press <kbd>Ctrl+C</kbd> to copy <mark>this</mark></p>");
+    /// ```
+    pub fn set_extra_spans(mut self, enabled: bool) -> Self {
+        self.extra_spans = enabled;
+        self
+    }
+
+    /// Enables math formulas, written either inline, between dollar signs
+    /// (`$E = mc^2$`), or as a block (`math. E = mc^2`, or `math..` for
+    /// a formula spanning several paragraphs). Textile doesn't touch
+    /// the content of a formula, so `*`, `_`, `-` or quotes stay intact.
+    ///
+    /// To tell formulas from prices, the opening `$` must be followed by
+    /// a non-space character, while the closing one must be preceded by it
+    /// and must not be followed by a letter or a digit.
+    ///
+    /// By default the formulas are wrapped into the delimiters recognized
+    /// by [KaTeX](https://katex.org/) and [MathJax](https://www.mathjax.org/),
+    /// so they can be rendered client-side: `\(...\)` within a
+    /// `<span class="math">` for the inline ones and `\[...\]` within
+    /// a `<div class="math">` for the blocks. A [`MathRenderer`] set by
+    /// [`Textile::set_math_renderer`] can render them on the server instead.
+    /// The `math.` block is not available in the lite mode.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let textile = Textile::default().set_math(true);
+    /// let html = textile.parse("Both $a_1 * b_1$ and $5 are fine.\n\nmath(eq). x^{*} - \"y\"");
+    /// assert_eq!(
+    ///     html,
+    ///     concat!(r#"<p>Both <span class="math">\(a_1 * b_1\)</span> and $5 are fine.</p>"#,
+    ///             "\n\n",
+    ///             r#"<div class="math eq">\[x^{*} - "y"\]</div>"#));
+    /// ```
+    pub fn set_math(mut self, enabled: bool) -> Self {
+        self.math = enabled;
+        self.update_block_tag_re();
+        self
+    }
+
+    /// Sets a [`MathRenderer`] for the formulas and enables them,
+    /// as [`Textile::set_math`] does.
+    pub fn set_math_renderer<R>(mut self, renderer: R) -> Self
+        where R: MathRenderer + 'static
+    {
+        self.math_renderer = Some(Box::new(renderer));
+        self.set_math(true)
+    }
+
+    /// Enables replacing the emoji shortcodes, like `:tada:` or `:+1:`,
+    /// with the emoji themselves (🎉 and 👍). The shortcodes are the ones
+    /// used by GitHub and Slack. Shortcodes within code, `notextile`
+    /// and URLs are left alone, as well as the unknown ones.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use rustextile::Textile;
+    /// let textile = Textile::default().set_emoji(true);
+    /// let html = textile.parse("Released :tada: at 12:30:00, see @:code:@");
+    /// assert_eq!(html, "<p>Released 🎉 at 12:30:00, see <code>:code:</code></p>");
+    /// ```
+    pub fn set_emoji(mut self, enabled: bool) -> Self {
+        self.emoji = enabled;
+        self
+    }
+
+    /// Adds a custom emoji shortcode (or overrides a standard one) and
+    /// enables the emoji, as [`Textile::set_emoji`] does.
+    /// The shortcode is given without the colons.
+    ///
+    /// An emoji given by its [image](CustomEmoji::Image) becomes an `<img>`
+    /// with the `emoji` class, built the same way as the Textile images are.
+    /// If the URL is not allowed (for instance, in the restricted mode,
+    /// or by [`Textile::set_image_hosts`]), the shortcode is left as is.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use rustextile::{Textile, CustomEmoji};
+    /// let textile = Textile::default()
+    ///     .add_emoji("parrot", CustomEmoji::Image("/emoji/parrot.gif".into()))
+    ///     .add_emoji("+1", CustomEmoji::Text("👌".into()));
+    /// let html = textile.parse(":parrot: :+1: :-1:");
+    /// assert_eq!(
+    ///     html,
+    ///     r#"<p><img alt=":parrot:" class="emoji" src="/emoji/parrot.gif" /> 👌 👎</p>"#);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the shortcode is empty or has any characters other than latin
+    /// letters, digits, `_`, `+` and `-`.
+    pub fn add_emoji(mut self, shortcode: &str, emoji: CustomEmoji) -> Self {
+        assert!(
+            !shortcode.is_empty()
+                && shortcode.chars().all(|c| c.is_ascii_alphanumeric() || "_+-".contains(c)),
+            "Invalid emoji shortcode: {:?}", shortcode);
+        self.custom_emoji.insert(shortcode.to_owned(), emoji);
+        self.set_emoji(true)
+    }
+
+    /// Sets an [`EntityResolver`], which turns the mentions (`@alice`)
+    /// and hashtags (`#release-2`) into links, provided the resolver knows
+    /// about them. The recognized ones are reported by
+    /// [`Textile::parse_document`].
+    ///
+    /// A mention or a hashtag must be preceded by a space, a parenthesis
+    /// or start a line, so neither e-mail addresses, nor `note#label.`
+    /// definitions, `[#label]` note references or `# list` items
+    /// get in the way. The inline code (`@code@`) takes precedence,
+    /// except when it starts with a known mention followed by a space
+    /// or a punctuation mark, as in `@alice and @bob`.
+    pub fn set_entity_resolver<R>(mut self, resolver: R) -> Self
+        where R: EntityResolver + 'static
+    {
+        self.entity_resolver = Some(Box::new(resolver));
+        self
+    }
+
+    /// Registers a new block signature, such as `note` for blocks like
+    /// `note. Text` or `note(important).. Text`, along with a function that
+    /// decides how such blocks are to be rendered.
+    ///
+    /// The handler receives the parsed [`CustomBlock`] and returns either
+    /// a ready HTML or asks the parser to process the content
+    /// as usual (see [`CustomBlockOutput`]). Custom blocks are not
+    /// recognized in the [lite mode](Textile::set_lite). A registered block
+    /// takes precedence over an optional built-in one with the same
+    /// signature, like `math` when [`Textile::set_math`] is enabled.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use rustextile::{Textile, CustomBlockOutput};
+    /// let textile = Textile::default()
+    ///     .register_block("note", |_block| {
+    ///         CustomBlockOutput::Textile {
+    ///             element: "aside".into(),
+    ///             attributes: vec![("class".into(), "note".into())],
+    ///         }
+    ///     })
+    ///     .register_block("map", |block| {
+    ///         CustomBlockOutput::Html(
+    ///             format!("<iframe src=\"/maps/{0}\"></iframe>", block.content))
+    ///     });
+    /// let html = textile.parse("note. Some *notable* text\n\nmap. paris");
+    /// assert_eq!(
+    ///     html,
+    ///     concat!("<aside class=\"note\">\n\t<p>Some <strong>notable</strong> text</p>\n</aside>\n\n",
+    ///             "<iframe src=\"/maps/paris\"></iframe>"));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the signature is not a lowercase latin word (digits, `-` and `_`
+    /// are allowed after the first letter) or clashes with a built-in one,
+    /// such as `p`, `bq` or `table`.
+    pub fn register_block<F>(mut self, signature: &str, handler: F) -> Self
+        where F: Fn(&CustomBlock) -> CustomBlockOutput + 'static
+    {
+        lazy_static! {
+            static ref SIGNATURE_RE: Regex = fregex!(r"^[a-z][a-z0-9_-]*$");
+            static ref BUILTIN_SIGNATURE_RE: Regex = fregex!(
+                &format!(r"^(?:{0}|table|notelist)$", BLOCK_TAGS_RE_S));
+        }
+        assert!(
+            SIGNATURE_RE.is_match(signature).unwrap_or_default()
+                && !BUILTIN_SIGNATURE_RE.is_match(signature).unwrap_or_default(),
+            "Invalid or reserved block signature: {:?}", signature);
+        self.custom_blocks.insert(signature.to_owned(), Box::new(handler));
+        self.update_block_tag_re();
+        self
+    }
+
+    /// Rebuilds the block signature pattern to include the optional ones:
+    /// custom blocks and the enabled built-in ones, like `math`.
+    fn update_block_tag_re(&mut self) {
+        let mut signatures: Vec<_> = self.custom_blocks.keys().map(|k| fancy_regex::escape(k)).collect();
+        let optional_signatures = [
+            (self.math, &["math"][..]),
+            (self.data_tables, &["csv", "tsv"][..]),
+            (self.details, &["details"][..]),
+            (self.containers, &["div", "section", "aside", "figure"][..]),
+            (self.figures, &["fig"][..]),
+            (self.media, &["video", "audio"][..]),
+        ];
+        for (enabled, names) in optional_signatures {
+            let names = names.iter().filter(|name| enabled && !self.custom_blocks.contains_key(**name));
+            signatures.extend(names.map(|name| Cow::Borrowed(*name)));
+        }
+        // Longer signatures go first, so "warning" wouldn't be matched as "warn"
+        signatures.sort_by_key(|k| std::cmp::Reverse(k.len()));
+        self.custom_block_tag_re = if signatures.is_empty() {
+            None
+        } else {
+            Some(textile_block_re(
+                &format!("{0}|{1}", signatures.join("|"), BLOCK_TAGS_RE_S)))
+        };
+    }
+
+    /// Registers a new kind of inline span, marked by the `open` and `close`
+    /// sequences, like `==highlighted==` (for which both of them are `"=="`)
+    /// or `[[kbd:Ctrl]]` (which opens with `"[[kbd:"` and closes with `"]]"`).
+    ///
+    /// The custom spans follow the same rules as the built-in ones
+    /// (like `*strong*`): they can have attributes, such as `||(big)text||`,
+    /// can be nested into each other and must be separated from the
+    /// surrounding text. The custom markers are recognized before
+    /// the built-in ones, in the order of their registration.
+    ///
+    /// Registering `"=="` disables the `==notextile==` shorthand.
+    /// Keep in mind that in the restricted mode the characters like `<`
+    /// or `&` are HTML-encoded before parsing, so the markers containing
+    /// them won't be recognized.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use rustextile::{Textile, SpanRenderer};
+    /// let textile = Textile::default()
+    ///     .register_span("==", "==", SpanRenderer::element("mark"))
+    ///     .register_span("[[kbd:", "]]", SpanRenderer::element("kbd"))
+    ///     .register_span("||", "||", SpanRenderer::Element {
+    ///         name: "span".into(),
+    ///         attributes: vec![("class".into(), "spoiler".into())],
+    ///     });
+    /// let html = textile.parse("Press [[kbd:Ctrl+C]] to ==copy *this*==, ||(big)he dies||");
+    /// assert_eq!(
+    ///     html,
+    ///     concat!("<p>Press <kbd>Ctrl+C</kbd> to <mark>copy <strong>this</strong></mark>, ",
+    ///             "<span class=\"spoiler big\">he dies</span></p>"));
+    /// ```
+    pub fn register_span(mut self, open: &str, close: &str, renderer: SpanRenderer) -> Self {
+        self.custom_spans.push(CustomSpanMarker {
+            open: open.to_owned(),
+            pattern: span_re_with(&fancy_regex::escape(open), &fancy_regex::escape(close)),
+            renderer,
+        });
+        self
+    }
+
     /// Allows to control a small random token which is used by the parser
     /// internally to construct unique HTML id attributes and links necessary
     /// for footnotes.
@@ -2161,7 +3892,7 @@ mod test {
     #[test]
     fn test_footnote_ref() {
         let t = super::Textile::default();
-        let mut state = super::ParserState::new(&t);
+        let mut state = super::ParserState::new(&t, "foo[1]", 0);
         let result = state.footnote_ref("foo[1]");
         let expect = format!(
             "foo<sup class=\"footnote\" id=\"fnrev{0}1\"><a href=\"#fn{0}1\">1</a></sup>",
@@ -56,4 +56,6 @@ lazy_static! {
     pub(crate) static ref LONE_AMP_RE: Regex = fregex!(r"(?i)&(?!#[0-9]+;|#x[a-f0-9]+;|[a-z][a-z0-9]*;)");
     pub(crate) static ref DIVIDER_RE: Regex = fregex!(
         r"(?si)^(?:</?(br|hr|img)(?:\s[^<>]*?|/?)>(?:</\1\s*?>)?)+$");
+    // a language of a code block or inline code, like "rust" in `bc[rust].`
+    pub(crate) static ref CODE_LANG_RE: Regex = fregex!(r"^[a-zA-Z0-9_-]+$");
 }
@@ -141,7 +141,7 @@ where I: Iterator<Item=&'a Regex> + 'a,
 }
 
 
-pub(crate) fn unwrap_or_empty(input: Option<Match>) -> &str{
+pub(crate) fn unwrap_or_empty(input: Option<Match<'_>>) -> &str {
     input.as_ref().map(Match::as_str).unwrap_or_default()
 }
 
@@ -0,0 +1,94 @@
+//! The `srcset` of the images served by a resizing proxy.
+
+type Resizer = dyn Fn(&str, u32) -> String;
+
+/// Makes every image carry a `srcset` of its resized copies, set by
+/// [`Textile::set_responsive_images`](crate::Textile::set_responsive_images).
+///
+/// The URL of a copy is built from the image's URL and the copy's width,
+/// either with a template or a function. The images also get
+/// `loading="lazy"` and `decoding="async"`, unless told otherwise,
+/// and the `sizes`, if given. The embedded `data:` images are left as they are.
+///
+/// ```rust
+/// use rustextile::{Textile, ResponsiveImages};
+/// let images = ResponsiveImages::with_resizer(
+///     |url: &str, width: u32| format!("/resize/{0}/{1}", width, url.trim_start_matches('/')),
+///     &[480, 960]);
+/// let html = Textile::default().set_responsive_images(images).parse("!/cat.jpg!");
+/// assert_eq!(
+///     html,
+///     concat!(r#"<p><img alt="" decoding="async" loading="lazy" src="/cat.jpg" "#,
+///             r#"srcset="/resize/480/cat.jpg 480w, /resize/960/cat.jpg 960w" /></p>"#));
+/// ```
+pub struct ResponsiveImages {
+    pub(crate) widths: Vec<u32>,
+    resizer: Box<Resizer>,
+    pub(crate) sizes: Option<String>,
+    pub(crate) lazy: bool,
+}
+
+impl ResponsiveImages {
+    /// Builds the URLs of the copies by replacing `{url}` and `{width}`
+    /// in the `template`, like `https://proxy.example.com/{width}/{url}`.
+    ///
+    /// # Panics
+    ///
+    /// If there are no `widths`, or some of them are zero.
+    pub fn with_template(template: &str, widths: &[u32]) -> Self {
+        let template = template.to_owned();
+        Self::with_resizer(
+            move |url: &str, width: u32| {
+                template
+                    .replace("{width}", &width.to_string())
+                    .replace("{url}", url)
+            },
+            widths)
+    }
+
+    /// Builds the URLs of the copies with a function receiving
+    /// the image's URL and the copy's width.
+    ///
+    /// # Panics
+    ///
+    /// If there are no `widths`, or some of them are zero.
+    pub fn with_resizer<F>(resizer: F, widths: &[u32]) -> Self
+        where F: Fn(&str, u32) -> String + 'static
+    {
+        assert!(
+            !widths.is_empty() && !widths.contains(&0),
+            "Invalid image widths: {:?}", widths);
+        Self {
+            widths: widths.to_vec(),
+            resizer: Box::new(resizer),
+            sizes: None,
+            lazy: true,
+        }
+    }
+
+    /// Sets the `sizes` attribute of the images, like
+    /// `(max-width: 600px) 100vw, 50vw`.
+    ///
+    /// ```rust
+    /// use rustextile::{Textile, ResponsiveImages};
+    /// let images = ResponsiveImages::with_template("/{width}/{url}", &[300])
+    ///     .set_sizes("50vw");
+    /// let html = Textile::default().set_responsive_images(images).parse("!cat.jpg!");
+    /// assert!(html.contains(r#"sizes="50vw" src="cat.jpg" srcset="/300/cat.jpg 300w""#));
+    /// ```
+    pub fn set_sizes(mut self, sizes: &str) -> Self {
+        self.sizes = Some(sizes.to_owned());
+        self
+    }
+
+    /// Enables or disables the `loading="lazy"` and `decoding="async"`
+    /// attributes, which are on by default.
+    pub fn set_lazy(mut self, lazy: bool) -> Self {
+        self.lazy = lazy;
+        self
+    }
+
+    pub(crate) fn resize(&self, url: &str, width: u32) -> String {
+        (self.resizer)(url, width)
+    }
+}
@@ -0,0 +1,53 @@
+//! The lines of the source text, to find where the things found
+//! in the normalized text come from.
+
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+
+use crate::regextra::fregex;
+
+/// The lines of the source text. The normalization of the text keeps
+/// its lines in place, so a line of the normalized text has the same
+/// number in the source, once the blank lines dropped from the beginning
+/// are accounted for.
+pub(crate) struct SourceLines<'s> {
+    /// The offset and the content of each line
+    lines: Vec<(usize, &'s str)>,
+    /// The number of blank lines dropped from the beginning
+    /// by the normalization
+    skipped: usize,
+}
+
+impl<'s> SourceLines<'s> {
+    /// Splits the `source`, which starts at the `base_offset`
+    /// of the whole text.
+    pub(crate) fn new(source: &'s str, base_offset: usize) -> Self {
+        lazy_static! {
+            static ref LINE_BREAK_RE: Regex = fregex!(r"\r\n?|\n");
+        }
+        let mut lines = Vec::new();
+        let mut line_start = 0;
+        for line_break in LINE_BREAK_RE.find_iter(source).flatten() {
+            lines.push((base_offset + line_start, &source[line_start..line_break.start()]));
+            line_start = line_break.end();
+        }
+        lines.push((base_offset + line_start, &source[line_start..]));
+        let skipped = lines.iter()
+            .take_while(|(_, line)| line.chars().all(|c| c == ' ' || c == '\t'))
+            .count();
+        Self { lines, skipped }
+    }
+
+    /// The offset and the content of the source line matching
+    /// the given line of the normalized text.
+    pub(crate) fn get(&self, line: usize) -> Option<(usize, &'s str)> {
+        self.lines.get(self.skipped + line).copied()
+    }
+
+    /// The offset of the first non-blank character of the source line
+    /// matching the given line of the normalized text.
+    pub(crate) fn offset(&self, line: usize) -> Option<usize> {
+        let (line_start, content) = self.get(line)?;
+        Some(line_start + content.len() - content.trim_start().len())
+    }
+}
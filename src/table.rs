@@ -3,10 +3,11 @@ use std::borrow::Cow;
 use fancy_regex::Regex;
 use lazy_static::lazy_static;
 
-use crate::block::{BlockAttributes, BlockHtmlAttributes};
+use crate::block::{BlockAttributes, BlockHtmlAttributes, extract_style_options};
 use crate::regextra::{split_with_capture, fregex};
 use crate::regex_snips::{ALIGN_RE_S, CLS_RE_S, VALIGN_RE_S, SNIP_SPACE, PNCT_RE_S};
-use crate::htmltools::generate_tag;
+use crate::htmltools::{generate_tag, encode_html, reverse_encode_html};
+use crate::parser::HtmlKind;
 
 
 const COLSPAN_RE_S: &str = r"(?:\\\d+)";
@@ -16,55 +17,570 @@ lazy_static! {
         r"(?:{0}|{1})*", COLSPAN_RE_S, ROWSPAN_RE_S);
 }
 
-fn process_caption(capts: &str, cap: &str, restricted: bool) -> String {
-    let html_attributes = BlockAttributes::parse(capts, None, true, restricted).html_attrs();
-    let tag = generate_tag("caption", Some(cap.trim()), &html_attributes);
-    format!("\t{0}\n", tag)
+/// Horizontal alignment of a table cell's content.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+    Justify,
 }
 
-struct TableSection {
-    tag: String,
-    attributes: BlockAttributes,
-    rows: Vec<String>
+/// Vertical alignment of a table cell's content.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerticalAlignment {
+    Top,
+    Middle,
+    Bottom,
 }
 
-impl TableSection {
-    fn new(tag: String, attributes: BlockAttributes) -> Self {
-        TableSection { tag, attributes, rows: Default::default() }
+/// How the summary of a table, given like `table. Summary`, is presented
+/// when the tables conform to HTML5, where the `summary` attribute
+/// is obsolete. Check [`Textile::set_html5_tables`](crate::Textile::set_html5_tables).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TableSummary {
+    /// The summary becomes the table's `caption`. A table which already
+    /// has a caption gets a [`TableSummary::Description`] instead.
+    #[default]
+    Caption,
+    /// The summary goes into a paragraph preceding the table,
+    /// which refers to it with its `aria-describedby` attribute.
+    Description,
+}
+
+/// A problem found in the grid of a table and fixed, when
+/// [`Textile::set_normalize_tables`](crate::Textile::set_normalize_tables)
+/// is enabled, or a record of the [CSV or TSV data](crate#csv-and-tsv-tables)
+/// which can't be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableDiagnostic {
+    /// The index of the table within [`Document::tables`](crate::Document::tables)
+    pub table: usize,
+    /// The index of the row, in the order the rows are written in,
+    /// not counting the caption and the column groups
+    pub row: usize,
+    /// The index of the column within the table's grid
+    pub column: usize,
+    /// The byte offset of the row within the source text,
+    /// pointing at its first character
+    pub offset: usize,
+    pub problem: TableProblem,
+}
+
+impl std::fmt::Display for TableDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Table {0}, row {1}, column {2}: {3}",
+               self.table + 1, self.row + 1, self.column + 1, self.problem)
+    }
+}
+
+/// What's wrong with a table, and how it has been fixed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TableProblem {
+    /// The row doesn't end with a `|`. It has been separated from the row
+    /// that follows instead of being merged with it.
+    MissingTrailingPipe,
+    /// The row has fewer cells than the table has columns
+    /// and has been padded with empty cells.
+    MissingCells { count: usize },
+    /// The cell spans more columns than there are left in its row.
+    /// Too large numbers are given as `usize::MAX`.
+    ColspanOverflow { colspan: usize, clamped_to: usize },
+    /// The cell spans more rows than there are left in its section.
+    /// Too large numbers are given as `usize::MAX`.
+    RowspanOverflow { rowspan: usize, clamped_to: usize },
+    /// The cell spans no columns or no rows, so the span has been dropped.
+    ZeroSpan,
+    /// The record of a `csv.` or `tsv.` block can't be read. Its raw line
+    /// has been put into a single cell.
+    MalformedRecord,
+}
+
+impl std::fmt::Display for TableProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingTrailingPipe => write!(f, "the row doesn't end with a |"),
+            Self::MissingCells { count: 1 } => write!(f, "the row lacks a cell"),
+            Self::MissingCells { count } => write!(f, "the row lacks {0} cells", count),
+            Self::ColspanOverflow { clamped_to, .. } =>
+                write!(f, "the cell spans more than the {0} columns left", clamped_to),
+            Self::RowspanOverflow { clamped_to, .. } =>
+                write!(f, "the cell spans more than the {0} rows left", clamped_to),
+            Self::ZeroSpan => write!(f, "the cell's span is 0"),
+            Self::MalformedRecord => write!(f, "the record can't be read"),
+        }
     }
+}
+
+/// A table, as written in Textile, before it's turned into HTML.
+///
+/// The tables of a document are available through
+/// [`Document::tables`](crate::Document::tables), and can be exported
+/// somewhere else, checked or rendered differently. The HTML of the
+/// content of the captions and the cells is final, with all the inline
+/// markup already processed. Rendering the table back with
+/// [`Table::to_html`] gives the same HTML the parser itself produces.
+///
+/// ```rust
+/// use rustextile::{Textile, TableSectionKind};
+/// let doc = Textile::default().parse_document(
+///     "|^.\n|_. Name |_. Score |\n|-.\n| *Alice* |>. 10 |\n| Bob |>. 7 |");
+/// let table = &doc.tables[0];
+/// assert_eq!(table.sections[0].kind, TableSectionKind::Head);
+/// let alice = &table.sections[1].rows[0].cells[0];
+/// assert_eq!(alice.content, " <strong>Alice</strong> ");
+/// assert!(!alice.header);
+/// assert_eq!(doc.html.trim_start(), table.to_html());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Table {
+    pub attributes: BlockAttributes,
+    /// The text following the attributes in `table(cls). Summary`,
+    /// rendered as the `summary` attribute
+    pub summary: Option<String>,
+    /// A paragraph describing the table, rendered before it
+    pub description: Option<TableDescription>,
+    pub caption: Option<TableCaption>,
+    /// Given by the rows starting with `|:`
+    pub column_groups: Vec<ColumnGroup>,
+    /// The `thead`, `tbody` and `tfoot` sections, in the order of appearance
+    pub sections: Vec<TableSection>,
+    /// The rows preceding the first section, if any. They are output
+    /// after all the sections.
+    pub rows: Vec<TableRow>,
+    /// The class of a `div` wrapping the table, if any.
+    /// Check [`Textile::set_table_wrapper`](crate::Textile::set_table_wrapper).
+    pub wrapper_class: Option<String>,
+}
+
+/// The description of a [`Table`], which the table refers to
+/// with its `aria-describedby` attribute
+#[derive(Clone, Debug, Default)]
+pub struct TableDescription {
+    /// The `id` of the paragraph holding the description
+    pub id: String,
+    /// The HTML content of the description
+    pub content: String,
+}
+
+/// The caption of a [`Table`], given by a first row like `|=. Caption`
+#[derive(Clone, Debug, Default)]
+pub struct TableCaption {
+    pub attributes: BlockAttributes,
+    /// The HTML content of the caption
+    pub content: String,
+}
+
+/// A `colgroup` of a [`Table`] along with its columns.
+#[derive(Clone, Debug, Default)]
+pub struct ColumnGroup {
+    pub attributes: BlockAttributes,
+    /// The attributes of each `col`
+    pub columns: Vec<BlockAttributes>,
+}
+
+/// The kind of a [`TableSection`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableSectionKind {
+    /// `thead`, started with `|^.`
+    Head,
+    /// `tbody`, started with `|-.`
+    Body,
+    /// `tfoot`, started with `|~.`
+    Foot,
+}
 
-    fn process(self) -> String {
-        let content = self.rows.join("") + "\n\t";
-        generate_tag(&self.tag, Some(&content), &self.attributes.html_attrs())
+impl TableSectionKind {
+    fn tag(self) -> &'static str {
+        match self {
+            Self::Head => "thead",
+            Self::Body => "tbody",
+            Self::Foot => "tfoot",
+        }
     }
 }
 
-struct Row {
-    cells: Vec<String>,
-    attributes: BlockHtmlAttributes,
+/// A group of rows of a [`Table`]
+#[derive(Clone, Debug)]
+pub struct TableSection {
+    pub kind: TableSectionKind,
+    pub attributes: BlockAttributes,
+    pub rows: Vec<TableRow>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TableRow {
+    pub attributes: BlockAttributes,
+    pub cells: Vec<TableCell>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TableCell {
+    /// Whether the cell is a `th` (given like `|_. Header|`)
+    /// rather than a `td`
+    pub header: bool,
+    /// The attributes, including the `colspan` and `rowspan` ones
+    /// and the alignment styles
+    pub attributes: BlockAttributes,
+    /// The `scope` of a header cell, if any
+    pub scope: Option<CellScope>,
+    /// The HTML content of the cell
+    pub content: String,
+}
+
+/// Which cells a header cell applies to, given by its `scope` attribute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellScope {
+    /// `scope="col"`, the cells below
+    Column,
+    /// `scope="row"`, the rest of the row
+    Row,
+}
+
+impl CellScope {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Column => "col",
+            Self::Row => "row",
+        }
+    }
 }
 
-impl Row {
-    fn new(attributes: BlockHtmlAttributes) -> Self {
-        Self {
-            attributes,
-            cells: Default::default(),
+impl TableCell {
+    /// The number of columns the cell spans, 1 by default.
+    pub fn colspan(&self) -> usize {
+        parse_span(self.attributes.colspan.as_deref())
+    }
+
+    /// The number of rows the cell spans, 1 by default.
+    pub fn rowspan(&self) -> usize {
+        parse_span(self.attributes.rowspan.as_deref())
+    }
+
+    /// The alignment set by the cell's `text-align` style, if any.
+    pub fn alignment(&self) -> Option<Alignment> {
+        match style_value(self.attributes.style.as_deref(), "text-align")? {
+            "left" => Some(Alignment::Left),
+            "center" => Some(Alignment::Center),
+            "right" => Some(Alignment::Right),
+            "justify" => Some(Alignment::Justify),
+            _ => None,
         }
     }
 
-    fn process(&self) -> String {
-        let cell_data = self.cells.join("") + "\n\t\t";
-        format!(
-            "\n\t\t{}",
-            generate_tag("tr", Some(&cell_data), &self.attributes))
+    /// The alignment set by the cell's `vertical-align` style, if any.
+    pub fn vertical_alignment(&self) -> Option<VerticalAlignment> {
+        match style_value(self.attributes.style.as_deref(), "vertical-align")? {
+            "top" => Some(VerticalAlignment::Top),
+            "middle" => Some(VerticalAlignment::Middle),
+            "bottom" => Some(VerticalAlignment::Bottom),
+            _ => None,
+        }
+    }
+}
+
+/// Drops a zero span and clamps the one exceeding the `limit`,
+/// returning the resulting span.
+fn normalize_span<O, R>(
+    span: &mut Option<String>,
+    limit: usize,
+    overflow: O,
+    mut report: R,
+) -> usize
+    where O: Fn(usize, usize) -> TableProblem,
+          R: FnMut(TableProblem)
+{
+    let value = match span.as_deref() {
+        Some(v) => v.parse::<usize>().unwrap_or(usize::MAX),
+        None => return 1,
+    };
+    if value == 0 {
+        report(TableProblem::ZeroSpan);
+        *span = None;
+        1
+    } else if value > limit {
+        report(overflow(value, limit));
+        *span = (limit > 1).then(|| limit.to_string());
+        limit
+    } else {
+        value
     }
 }
 
+fn parse_span(value: Option<&str>) -> usize {
+    value.and_then(|v| v.parse().ok()).unwrap_or(1)
+}
+
+/// Finds the last value given to a property within a style declaration.
+fn style_value<'s>(style: Option<&'s str>, property: &str) -> Option<&'s str> {
+    style?
+        .split(';')
+        .rev()
+        .filter_map(|declaration| declaration.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case(property))
+        .map(|(_, value)| value.trim())
+}
+
+impl Table {
+    /// Renders the table into HTML.
+    pub fn to_html(&self) -> String {
+        self.render(None)
+    }
+
+    /// Renders the table, optionally putting the markers like
+    /// `{uid}{table_index}.{item_index}:cell` in place of the content of
+    /// the description, the caption and the cells, so the content
+    /// would be inserted once it's final.
+    fn render(&self, marker: Option<(&str, usize)>) -> String {
+        let mut item_index = 0;
+        let mut tag_with_content = |tag: &str, content: &str, attributes: &BlockHtmlAttributes| {
+            let html = match marker {
+                Some((uid, table_index)) => generate_tag(
+                    tag,
+                    Some(&format!("{0}{1}.{2}:cell", uid, table_index, item_index)),
+                    attributes),
+                None => generate_tag(tag, Some(content), attributes),
+            };
+            item_index += 1;
+            html
+        };
+
+        let mut html_attrs = self.attributes.clone().html_attrs();
+        if let Some(ref summary) = self.summary {
+            html_attrs.insert("summary", summary.clone());
+        }
+        let description = self.description.as_ref().map(|description| {
+            html_attrs.insert("aria-describedby", description.id.clone());
+            let mut attrs = BlockHtmlAttributes::default();
+            attrs.insert("id", description.id.clone());
+            tag_with_content("p", &description.content, &attrs)
+        });
+        let mut content = String::new();
+        if let Some(ref caption) = self.caption {
+            let tag = tag_with_content(
+                "caption", &caption.content, &caption.attributes.clone().html_attrs());
+            content.push_str(&format!("\n\t{0}\n", tag));
+        }
+        for group in self.column_groups.iter() {
+            content.push_str("\t<colgroup");
+            content.push_str(&String::from(group.attributes.clone()));
+            content.push_str(">\n");
+            for col in group.columns.iter() {
+                content.push_str("\t<col");
+                content.push_str(&String::from(col.clone()));
+                content.push_str(" />\n");
+            }
+            content.push_str("\t</colgroup>");
+        }
+        let sections = self.sections.iter()
+            .map(|section| (Some(section), &section.rows))
+            .chain(std::iter::once((None, &self.rows)));
+        for (section, rows) in sections {
+            let mut rows_html = String::new();
+            for row in rows.iter() {
+                let mut cells = String::new();
+                for cell in row.cells.iter() {
+                    let ctag = if cell.header { "th" } else { "td" };
+                    let mut attrs = cell.attributes.clone().html_attrs();
+                    if let Some(scope) = cell.scope {
+                        attrs.insert("scope", scope.as_str().to_owned());
+                    }
+                    cells.push_str("\n\t\t\t");
+                    cells.push_str(&tag_with_content(ctag, &cell.content, &attrs));
+                }
+                cells.push_str("\n\t\t");
+                rows_html.push_str("\n\t\t");
+                rows_html.push_str(
+                    &generate_tag("tr", Some(&cells), &row.attributes.clone().html_attrs()));
+            }
+            match section {
+                Some(section) => {
+                    rows_html.push_str("\n\t");
+                    content.push_str("\n\t");
+                    content.push_str(
+                        &generate_tag(
+                            section.kind.tag(),
+                            Some(&rows_html),
+                            &section.attributes.clone().html_attrs()));
+                },
+                None => content.push_str(&rows_html),
+            }
+        }
+        content.push_str("\n\t");
+        let mut html = generate_tag("table", Some(&content), &html_attrs);
+        if let Some(description) = description {
+            html = format!("{0}\n\t{1}", description, html);
+        }
+        if let Some(ref class) = self.wrapper_class {
+            html = generate_tag(
+                "div",
+                Some(&format!("\n\t{0}\n\t", html)),
+                &[("class".to_owned(), class.clone())]);
+        }
+        html
+    }
+
+    /// Replaces the features of the table which are obsolete in HTML5:
+    /// the summary is turned into the caption or the description,
+    /// the widths of the columns into their styles, and the header cells
+    /// get their scopes.
+    pub(crate) fn conform_to_html5<F>(&mut self, summary_kind: TableSummary, new_id: F)
+        where F: FnOnce() -> String
+    {
+        if let Some(summary) = self.summary.take() {
+            match (summary_kind, &self.caption) {
+                (TableSummary::Caption, None) => {
+                    self.caption = Some(TableCaption { content: summary, ..Default::default() });
+                },
+                _ => {
+                    self.description = Some(TableDescription { id: new_id(), content: summary });
+                },
+            }
+        }
+        let columns = self.column_groups.iter_mut()
+            .flat_map(|group| std::iter::once(&mut group.attributes).chain(group.columns.iter_mut()));
+        for attributes in columns {
+            if let Some(width) = attributes.width.take() {
+                let width = format!("width:{0}px;", width);
+                attributes.style = Some(match attributes.style.take() {
+                    Some(style) => format!("{0} {1}", style, width),
+                    None => width,
+                });
+            }
+        }
+        let groups = self.sections.iter_mut()
+            .map(|section| (Some(section.kind), &mut section.rows))
+            .chain(std::iter::once((None, &mut self.rows)));
+        for (kind, rows) in groups {
+            for row in rows.iter_mut() {
+                let is_header_row = kind == Some(TableSectionKind::Head)
+                    || row.cells.iter().all(|cell| cell.header);
+                for cell in row.cells.iter_mut().filter(|cell| cell.header) {
+                    cell.scope = Some(if is_header_row { CellScope::Column } else { CellScope::Row });
+                }
+            }
+        }
+    }
 
+    /// Makes the table's grid rectangular: pads the short rows with empty
+    /// cells, clamps the spans which don't fit into the grid and drops
+    /// the zero ones. As in HTML validators, the number of columns
+    /// is established by the first row, though it's never less than needed
+    /// for any row to have all its cells. The rowspans are limited
+    /// to their sections.
+    pub(crate) fn normalize(&mut self) -> Vec<TableDiagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut report = |row, column, problem| {
+            diagnostics.push(TableDiagnostic { table: 0, row, column, offset: 0, problem });
+        };
+
+        // The rows before the first section are written first
+        let groups = std::iter::once(&self.rows)
+            .chain(self.sections.iter().map(|s| &s.rows));
+        let mut width = 0;
+        let mut is_first_row = true;
+        for rows in groups {
+            // How many more rows each column is covered for by the cells above
+            let mut covered = Vec::<usize>::new();
+            for (row_index, row) in rows.iter().enumerate() {
+                let row_width = if is_first_row {
+                    row.cells.iter().map(TableCell::colspan).fold(0, usize::saturating_add)
+                } else {
+                    row.cells.len() + covered.iter().filter(|c| **c > 0).count()
+                };
+                width = width.max(row_width);
+                is_first_row = false;
+
+                let mut spanned = Vec::<usize>::new();
+                let mut column = 0;
+                for cell in row.cells.iter() {
+                    while covered.get(column).copied().unwrap_or_default() > 0 {
+                        column += 1;
+                    }
+                    let colspan = cell.colspan().min(width.max(column + 1) - column);
+                    let rowspan = cell.rowspan().min(rows.len() - row_index);
+                    spanned.resize(spanned.len().max(column + colspan), 0);
+                    spanned[column..column + colspan].fill(rowspan);
+                    column += colspan;
+                }
+                covered.resize(covered.len().max(spanned.len()), 0);
+                for (c, s) in covered.iter_mut().zip(spanned.into_iter().chain(std::iter::repeat(0))) {
+                    *c = c.saturating_sub(1).max(s.saturating_sub(1));
+                }
+            }
+        }
+
+        let mut global_row = 0;
+        let groups = std::iter::once(&mut self.rows)
+            .chain(self.sections.iter_mut().map(|s| &mut s.rows));
+        for rows in groups {
+            let group_len = rows.len();
+            // How many more rows each column is covered for by the cells above
+            let mut covered = vec![0usize; width];
+            for (row_index, row) in rows.iter_mut().enumerate() {
+                let mut spanned = vec![0usize; width];
+                let mut column = 0;
+                for cell in row.cells.iter_mut() {
+                    while column < width && covered[column] > 0 {
+                        column += 1;
+                    }
+                    let columns_left = width.saturating_sub(column).max(1);
+                    let rows_left = group_len - row_index;
+                    let colspan = normalize_span(
+                        &mut cell.attributes.colspan, columns_left,
+                        |colspan, clamped_to| TableProblem::ColspanOverflow { colspan, clamped_to },
+                        |problem| report(global_row, column, problem));
+                    let rowspan = normalize_span(
+                        &mut cell.attributes.rowspan, rows_left,
+                        |rowspan, clamped_to| TableProblem::RowspanOverflow { rowspan, clamped_to },
+                        |problem| report(global_row, column, problem));
+                    for span in spanned.iter_mut().skip(column).take(colspan) {
+                        *span = rowspan;
+                    }
+                    column += colspan;
+                }
+                let missing: Vec<usize> = (column..width)
+                    .filter(|c| covered[*c] == 0)
+                    .collect();
+                if let Some(first_missing) = missing.first() {
+                    report(global_row, *first_missing, TableProblem::MissingCells { count: missing.len() });
+                    let header = row.cells.last().map(|c| c.header).unwrap_or_default();
+                    row.cells.extend(
+                        missing.iter().map(|_| TableCell { header, ..Default::default() }));
+                }
+                for (c, s) in covered.iter_mut().zip(spanned) {
+                    *c = c.saturating_sub(1).max(s.saturating_sub(1));
+                }
+                global_row += 1;
+            }
+        }
+        diagnostics
+    }
+
+    /// The HTML content of the description, the caption and the cells,
+    /// in the order of their appearance in the output.
+    pub(crate) fn contents_mut(&mut self) -> impl Iterator<Item=&mut String> {
+        self.description.iter_mut()
+            .map(|description| &mut description.content)
+            .chain(self.caption.iter_mut().map(|caption| &mut caption.content))
+            .chain(
+                self.sections.iter_mut()
+                    .flat_map(|section| section.rows.iter_mut())
+                    .chain(self.rows.iter_mut())
+                    .flat_map(|row| row.cells.iter_mut())
+                    .map(|cell| &mut cell.content))
+    }
+}
+
+
+/// Turns the rows of a table, starting at the line `first_line`
+/// of the document, into the table.
 pub(crate) fn process_table<'t>(
     parser: &mut crate::parser::ParserState,
     tatts: &'t str,
     rows_str: &'t str,
+    first_line: usize,
     summary: Option<&'t str>
 ) -> String
 {
@@ -91,43 +607,44 @@ pub(crate) fn process_table<'t>(
         static ref CMTCH_RE: Regex = fregex!(
             &format!(r"(?s)^(?P<catts>_?{0}{1}{2}\. )(?P<cell>.*)",
                      *TABLE_SPAN_RE_S, *ALIGN_RE_S, *CLS_RE_S));
-        static ref CELL_A_PATTERN_RE: Regex = fregex!(
-            &format!(r"(?s)(?P<space>{0}*)(?P<cell>.*)", SNIP_SPACE));
         static ref COLGROUP_RE: Regex = fregex!(
             &format!(r"(?m)^\|:(?P<cols>{s}{a}{c}\. .*)",
                      s=*TABLE_SPAN_RE_S, a=*ALIGN_RE_S, c=*CLS_RE_S));
         static ref HEADING_RE: Regex = fregex!(
             &format!(r"^_(?={0}|{1})", SNIP_SPACE, PNCT_RE_S));
+        static ref UNTERMINATED_ROW_RE: Regex = fregex!(r"\n(?=[ \t]*\|)");
     }
-    let mut html_attrs = BlockAttributes::parse(tatts, Some("table"), true, parser.textile.restricted).html_attrs();
-
-    if let Some(s) = summary {
-        if !s.is_empty() {
-            html_attrs.insert("summary", s.trim().to_owned());
-        }
-    }
-    let mut caption = String::new();
-    let mut colgroup = String::new();
-    let mut content = Vec::<String>::new();
-    let mut rgrp: Option<TableSection> = None;
-    let mut groups = Vec::<String>::new();
+    let restricted = parser.textile.restricted;
+    let mut table = Table {
+        attributes: BlockAttributes::parse(tatts, Some("table"), true, restricted),
+        summary: summary.map(str::trim).filter(|s| !s.is_empty()).map(str::to_owned),
+        ..Default::default()
+    };
 
-    let non_empty_rows =
+    let mut diagnostics = Vec::new();
+    // The lines each of the rows start at, in the order they are written in
+    let mut row_lines = Vec::new();
+    let mut line = first_line;
+    let non_empty_rows: Vec<(usize, &str)> =
         split_with_capture(&COMPONENTS_RE, rows_str)
-        .filter(|row| !row.is_empty());
-    for (i, row) in non_empty_rows.enumerate() {
+        .filter_map(|row| {
+            line += row.matches('\n').count();
+            // Along with the line the row ends at
+            (!row.is_empty()).then_some((line, row))
+        })
+        .collect();
+    let rows_count = non_empty_rows.len();
+    for (i, (end_line, row)) in non_empty_rows.into_iter().enumerate() {
         let row = Cow::Borrowed(row.trim_start());
 
         // # Caption -- only occurs on row 1, otherwise treat '|=. foo |...'
         // # as a normal center-aligned cell.
         let row = if i == 0 {
             if let Ok(Some(cmtch)) = CAPTION_RE.captures(&row) {
-                caption = format!(
-                    "\n{}",
-                    process_caption(
-                        &cmtch["capts"],
-                        &cmtch["cap"],
-                        parser.textile.restricted));
+                table.caption = Some(TableCaption {
+                    attributes: BlockAttributes::parse(&cmtch["capts"], None, true, restricted),
+                    content: cmtch["cap"].trim().to_owned(),
+                });
                 let new_row = cmtch["row"].trim_start();
                 if new_row.is_empty() {continue} else {new_row.to_owned().into()}
             } else {
@@ -142,22 +659,13 @@ pub(crate) fn process_table<'t>(
             // Is this colgroup def missing a closing pipe? If so, there
             // will be a newline in the middle of $row somewhere.
             let cols = &gmtch[1].replace('.', "");
-            for (idx, col) in cols.split('|').enumerate() {
-                let group_atts: String = BlockAttributes
-                    ::parse(col.trim(), Some("col"), true, parser.textile.restricted)
-                    .into();
-                colgroup.push_str("\t<col");
-                if idx == 0 {
-                    colgroup.push_str("group");
-                    colgroup.push_str(&group_atts);
-                    colgroup.push('>');
-                } else {
-                    colgroup.push_str(&group_atts);
-                    colgroup.push_str(" />");
-                }
-                colgroup.push('\n');
-            }
-            colgroup.push_str("\t</colgroup>");
+            let mut cols = cols
+                .split('|')
+                .map(|col| BlockAttributes::parse(col.trim(), Some("col"), true, restricted));
+            table.column_groups.push(ColumnGroup {
+                attributes: cols.next().unwrap_or_default(),
+                columns: cols.collect(),
+            });
             let row_newline = row.find('\n');
             if let Some(nl_index) = row_newline {
                 Cow::Borrowed(row[nl_index..].trim_start())
@@ -172,93 +680,267 @@ pub(crate) fn process_table<'t>(
         let row = if let Ok(Some(ref grpmatch)) = grpmatch_cap {
             if let (Some(grpname), Some(rgrpatts))
                 = (grpmatch.name("part"), grpmatch.name("rgrpatts")) {
-                // we're about to start a new group, so process the current one
-                // and add it to the output
-                if let Some(rgrp_data) = rgrp {
-                    groups.push(format!("\n\t{0}", rgrp_data.process()));
-                }
-                let section_tag = match grpname.as_str() {
-                    "^" => "thead",
-                    "~" => "tfoot",
-                    "-" => "tbody",
+                // we're about to start a new group
+                let kind = match grpname.as_str() {
+                    "^" => TableSectionKind::Head,
+                    "~" => TableSectionKind::Foot,
+                    "-" => TableSectionKind::Body,
                     _ => unreachable!()
                 };
-                rgrp = Some(
-                    TableSection::new(
-                        section_tag.to_owned(),
-                        BlockAttributes::parse(
-                            rgrpatts.as_str(),
-                            None,
-                            true,
-                            parser.textile.restricted)));
+                table.sections.push(TableSection {
+                    kind,
+                    attributes: BlockAttributes::parse(rgrpatts.as_str(), None, true, restricted),
+                    rows: Vec::new(),
+                });
             }
             Cow::Borrowed(&grpmatch["row"])
         } else {
             row
         };
 
+        // Whatever has been taken from the row is at its beginning
+        let mut piece_line = end_line - row.matches('\n').count();
+
         let rmtch_cap = RMTCH_RE.captures(row.trim_start());
         let (row, row_atts) = match rmtch_cap {
             Ok(Some(ref rmtch)) => (
                 Cow::Borrowed(&rmtch["row"]),
-                BlockAttributes::parse(&rmtch["ratts"], Some("tr"), true, parser.textile.restricted).html_attrs()
+                BlockAttributes::parse(&rmtch["ratts"], Some("tr"), true, restricted)
             ),
-            _ => (row, BlockHtmlAttributes::default()),
+            _ => (row, BlockAttributes::default()),
         };
 
-        // create a row to hold the cells.
-        let mut r = Row::new(row_atts);
-        for (_cellctr, cell) in row.split('|').skip(1).enumerate() {
-            let ctag = match HEADING_RE.is_match(cell) {
-                Ok(true) => "th",
-                _ => "td"
+        // A row missing its trailing "|" gets merged with the next one.
+        // When normalizing, they are split apart again.
+        let pieces: Vec<&str> = if parser.textile.normalize_tables {
+            split_with_capture(&UNTERMINATED_ROW_RE, &row).collect()
+        } else {
+            vec![&row]
+        };
+        let mut row_atts = Some(row_atts);
+        for (piece_index, piece) in pieces.iter().enumerate() {
+            // create a row to hold the cells.
+            let mut r = TableRow {
+                attributes: row_atts.take().unwrap_or_default(),
+                cells: Vec::new(),
             };
+            for cell in piece.split('|').skip(1) {
+                let header = HEADING_RE.is_match(cell).unwrap_or_default();
 
-            let cmtch_cap = CMTCH_RE.captures(cell);
-            let (cell, cell_atts) = match cmtch_cap {
-                Ok(Some(ref cmtch)) => (
-                    &cmtch["cell"],
-                    BlockAttributes::parse(&cmtch["catts"], Some("td"), true, parser.textile.restricted).html_attrs()
-                ),
-                _ => (cell, BlockHtmlAttributes::default())
-            };
+                let cmtch_cap = CMTCH_RE.captures(cell);
+                let (cell, cell_atts) = match cmtch_cap {
+                    Ok(Some(ref cmtch)) => (
+                        &cmtch["cell"],
+                        BlockAttributes::parse(&cmtch["catts"], Some("td"), true, restricted)
+                    ),
+                    _ => (cell, BlockAttributes::default())
+                };
 
-            let cell = if !parser.textile.lite {
-                Cow::Owned(
-                    if let Ok(Some(a)) = CELL_A_PATTERN_RE.captures(cell) {
-                        let cell = parser.redcloth_list(&a["cell"]);
-                        let cell = parser.textile_lists(&cell);
-                        a["space"].to_owned() + cell.as_ref()
-                    } else {
-                        String::new()
-                    }
-                )
-            } else {
-                Cow::Borrowed(cell)
-            };
+                // add the cell to the row
+                r.cells.push(TableCell {
+                    header,
+                    attributes: cell_atts,
+                    scope: None,
+                    content: cell_content(parser, cell),
+                });
+            }
+            let is_unterminated = piece_index + 1 < pieces.len()
+                || (i + 1 == rows_count && !rows_str.trim_end().ends_with('|'));
+            if is_unterminated && parser.textile.normalize_tables {
+                diagnostics.push(TableDiagnostic {
+                    table: 0,
+                    row: row_lines.len(),
+                    column: r.cells.len(),
+                    offset: 0,
+                    problem: TableProblem::MissingTrailingPipe,
+                });
+            }
+            row_lines.push(piece_line);
+            piece_line += piece.matches('\n').count() + 1;
+            // if we're in a group, add it to the group's rows, else add it
+            // directly to the table
+            match table.sections.last_mut() {
+                Some(section) => section.rows.push(r),
+                None => table.rows.push(r),
+            }
+        }
+    }
 
-            // create a cell
-            let c = generate_tag(ctag, Some(&cell), &cell_atts);
-            let cline_tag = format!("\n\t\t\t{0}", c);
-            // add the cell to the row
-            r.cells.push(parser.do_tag_br(ctag, &cline_tag).into_owned());
+    emit_table(parser, table, diagnostics, &row_lines)
+}
+
+/// Turns the content of a `csv.` or `tsv.` block into a table.
+///
+/// Besides the usual attributes of the table, the style part of the block's
+/// attributes may contain the `delimiter` of the values (given either as
+/// a single character or as one of `comma`, `semicolon`, `tab`, `pipe`
+/// and `space`) and the `header` flag, which turns the first row into
+/// the table's `thead`, like in `csv(prices){delimiter: semicolon; header}.`
+pub(crate) fn process_data_table(
+    parser: &mut crate::parser::ParserState,
+    signature: &str,
+    attrs: &str,
+    data: &str
+) -> String {
+    let restricted = parser.textile.restricted;
+    let (attrs, options) = extract_style_options(attrs, &["delimiter", "header"]);
+    let mut delimiter = if signature == "tsv" { b'\t' } else { b',' };
+    let mut has_header = false;
+    for (property, value) in options {
+        match property {
+            "delimiter" => {
+                let named = match value {
+                    "comma" => Some(b','),
+                    "semicolon" => Some(b';'),
+                    "tab" => Some(b'\t'),
+                    "pipe" => Some(b'|'),
+                    "space" => Some(b' '),
+                    _ => None,
+                };
+                let single = match value.as_bytes() {
+                    [c] if c.is_ascii_punctuation() && *c != b'"' => Some(*c),
+                    _ => None,
+                };
+                delimiter = named.or(single).unwrap_or(delimiter);
+            },
+            _ => has_header = matches!(value, "" | "on" | "yes" | "true"),
         }
-        // if we're in a group, add it to the group's rows, else add it
-        // directly to the content
-        if let Some(ref mut rgrp_data) = rgrp {
-            rgrp_data.rows.push(r.process());
-        } else {
-            content.push(r.process());
+    }
+
+    // In the restricted mode the data is already HTML-encoded,
+    // and the entities like `&amp;` mustn't be split by a `;` delimiter.
+    let data = if restricted { reverse_encode_html(data) } else { data.into() };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+    let mut rows = Vec::new();
+    let mut row_lines = Vec::new();
+    let mut diagnostics = Vec::new();
+    for record in reader.records() {
+        let header = has_header && rows.is_empty();
+        let position = match record {
+            Ok(ref record) => record.position(),
+            Err(ref err) => err.position(),
+        };
+        // The numbers of the lines are 1-based
+        let line = position.map(|p| p.line().saturating_sub(1) as usize).unwrap_or_default();
+        row_lines.push(parser.block_line + line);
+        // A record which can't be read is kept as a single cell
+        // holding its raw line
+        let fields = match record {
+            Ok(record) => record.iter().map(str::to_owned).collect(),
+            Err(_) => {
+                diagnostics.push(TableDiagnostic {
+                    table: 0,
+                    row: rows.len(),
+                    column: 0,
+                    offset: 0,
+                    problem: TableProblem::MalformedRecord,
+                });
+                vec![data.lines().nth(line).unwrap_or_default().to_owned()]
+            },
+        };
+        let mut row = TableRow::default();
+        for field in fields {
+            let field = if restricted { encode_html(&field, false, false) } else { field };
+            // The values are inline text, so no lists are looked for
+            row.cells.push(TableCell {
+                header,
+                attributes: BlockAttributes::default(),
+                scope: None,
+                content: parser.do_br(&field).into_owned(),
+            });
         }
+        rows.push(row);
     }
-    // if there's still an rgrp, process it and add it to the output
-    if let Some(rgrp_data) = rgrp {
-        groups.push(format!("\n\t{0}", rgrp_data.process()));
+
+    let mut table = Table {
+        attributes: BlockAttributes::parse(&attrs, Some("table"), true, restricted),
+        ..Default::default()
+    };
+    if has_header && !rows.is_empty() {
+        let body = rows.split_off(1);
+        table.sections.push(TableSection {
+            kind: TableSectionKind::Head,
+            attributes: BlockAttributes::default(),
+            rows,
+        });
+        if !body.is_empty() {
+            table.sections.push(TableSection {
+                kind: TableSectionKind::Body,
+                attributes: BlockAttributes::default(),
+                rows: body,
+            });
+        }
+    } else {
+        table.rows = rows;
     }
+    emit_table(parser, table, diagnostics, &row_lines)
+}
 
-    let tag_content = format!(
-        "{0}{1}{2}{3}\n\t",
-        caption, colgroup, groups.join(""), content.join(""));
-    let tbl = generate_tag("table", Some(&tag_content), &html_attrs);
-    format!("\t{0}\n\n", tbl)
+/// Processes the lists and the line breaks within the content of a cell.
+fn cell_content(parser: &mut crate::parser::ParserState, cell: &str) -> String {
+    lazy_static! {
+        static ref CELL_A_PATTERN_RE: Regex = fregex!(
+            &format!(r"(?s)(?P<space>{0}*)(?P<cell>.*)", SNIP_SPACE));
+    }
+    let cell = if !parser.textile.lite {
+        Cow::Owned(
+            if let Ok(Some(a)) = CELL_A_PATTERN_RE.captures(cell) {
+                let cell = parser.redcloth_list(&a["cell"]);
+                // The tasks are only looked for at the beginnings of the lines
+                // of a block, so the lists within the cells don't have any
+                let pending_tasks = std::mem::take(&mut parser.pending_tasks);
+                let cell = parser.textile_lists(&cell);
+                parser.pending_tasks = pending_tasks;
+                a["space"].to_owned() + cell.as_ref()
+            } else {
+                String::new()
+            }
+        )
+    } else {
+        Cow::Borrowed(cell)
+    };
+    parser.do_br(&cell).into_owned()
+}
+
+/// Renders the table as a block of the output, keeping its model
+/// to be returned within the [`Document`](crate::Document),
+/// and normalizing it and making it conform to HTML5 beforehand
+/// if necessary. The diagnostics get their positions from the lines
+/// the rows start at.
+fn emit_table(
+    parser: &mut crate::parser::ParserState,
+    mut table: Table,
+    mut diagnostics: Vec<TableDiagnostic>,
+    row_lines: &[usize]
+) -> String {
+    let table_index = parser.tables.len();
+    if parser.textile.normalize_tables {
+        diagnostics.extend(table.normalize());
+        diagnostics.sort_by_key(|d| (d.row, d.column));
+    }
+    for mut diagnostic in diagnostics {
+        diagnostic.table = table_index;
+        let line = row_lines.get(diagnostic.row).copied().unwrap_or(parser.block_line);
+        diagnostic.offset = parser.source_lines.offset(line).unwrap_or_default();
+        parser.table_diagnostics.push(diagnostic);
+    }
+    if parser.textile.html5_tables && matches!(parser.textile.html_type, HtmlKind::HTML5) {
+        table.conform_to_html5(parser.textile.table_summary, || {
+            let new_index = parser.increment_link_index();
+            format!("tabledesc{0}{1}", parser.textile.link_prefix, new_index)
+        });
+    }
+    table.wrapper_class = parser.textile.table_wrapper.clone();
+    // The content is kept out of the rest of the block's processing
+    for content in table.contents_mut() {
+        *content = parser.phrases(content);
+    }
+    let html = table.render(Some((&parser.textile.uid, table_index)));
+    parser.tables.push(table);
+    format!("\t{0}\n\n", html)
 }
@@ -0,0 +1,70 @@
+use std::collections::VecDeque;
+
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+
+use crate::regextra::fregex;
+use crate::regex_snips::CLS_RE_S;
+use crate::source::SourceLines;
+
+lazy_static! {
+    static ref TASK_LINE_RE: Regex = fregex!(
+        &format!(
+            concat!(r"^[ \t]*[#*]+(?:_|\d+)?{0}[ .][ \t]*(?P<box>\[(?P<state>[ xX])\])",
+                    r"(?:[ \t]+(?P<text>.*))?$"),
+            *CLS_RE_S));
+}
+
+/// An item of a task list, written like `* [ ] To do` or `* [x] Done`,
+/// when [`Textile::set_task_lists`](crate::Textile::set_task_lists)
+/// is enabled.
+///
+/// The tasks of a document are available through
+/// [`Document::tasks`](crate::Document::tasks), in the order
+/// of their appearance.
+///
+/// ```rust
+/// use rustextile::Textile;
+/// let source = "Chores:\n\n* [x] Dishes\n* [ ] Laundry";
+/// let doc = Textile::default().set_task_lists(true).parse_document(source);
+/// let laundry = &doc.tasks[1];
+/// assert_eq!(laundry.text, "Laundry");
+/// assert!(!laundry.checked);
+/// assert_eq!(&source[laundry.offset..], "[ ] Laundry");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Task {
+    /// The text following the checkbox on its line, as written in the source
+    pub text: String,
+    /// Whether the box is checked, with `[x]` or `[X]`
+    pub checked: bool,
+    /// The byte offset of the checkbox's `[` within the source text,
+    /// which is where the box may be toggled
+    pub offset: usize,
+}
+
+impl SourceLines<'_> {
+    /// Finds the tasks within a block of the normalized text,
+    /// whose first line is `first_line`.
+    pub(crate) fn locate_tasks(&self, block: &str, first_line: usize) -> VecDeque<Task> {
+        let mut tasks = VecDeque::new();
+        for (index, line) in block.split('\n').enumerate() {
+            if !TASK_LINE_RE.is_match(line).unwrap_or_default() {
+                continue;
+            }
+            let source_line = self.get(first_line + index);
+            let task = source_line.and_then(|(line_start, source_line)| {
+                let cap = TASK_LINE_RE.captures(source_line).ok()??;
+                Some(Task {
+                    text: cap.name("text").map(|m| m.as_str().trim()).unwrap_or_default().to_owned(),
+                    checked: &cap["state"] != " ",
+                    offset: line_start + cap.name("box")?.start(),
+                })
+            });
+            // Unless the encoding of the restricted mode has made
+            // the lines differ, which isn't expected to happen
+            tasks.extend(task);
+        }
+        tasks
+    }
+}
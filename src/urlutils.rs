@@ -12,7 +12,7 @@
 use std::borrow::Cow;
 
 use lazy_static::lazy_static;
-use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
 
 use crate::htmltools::encode_html;
 
@@ -36,6 +36,10 @@ const PSEUDO_BASE: &str = "http://example.com";
 lazy_static! {
     static ref BASE: url::Url = url::Url::parse(PSEUDO_BASE)
         .expect("A valid url");
+    // Another base, to tell the hosts of scheme-relative URLs
+    // (`//example.org/path`) from the base's own one
+    static ref OTHER_BASE: url::Url = url::Url::parse("http://example.net")
+        .expect("A valid url");
 }
 
 impl UrlBits {
@@ -75,12 +79,28 @@ impl UrlBits {
             UrlBits::RelativeUrl {..} => true,
         }
     }
+
+    /// The host of an absolute URL or of a scheme-relative one,
+    /// like `//example.com/path`.
+    pub fn host(&self) -> Option<&str> {
+        match self {
+            UrlBits::AbsoluteUrl(url) => url.host_str(),
+            UrlBits::RelativeUrl { url, source } => {
+                let other_url = OTHER_BASE.join(source).ok()?;
+                if other_url.host_str() == url.host_str() {
+                    url.host_str()
+                } else {
+                    None
+                }
+            },
+        }
+    }
 }
 
 
-impl ToString for UrlBits {
-    fn to_string(&self) -> String {
-        match self {
+impl std::fmt::Display for UrlBits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
             UrlBits::AbsoluteUrl(url) => url.to_string(),
             UrlBits::RelativeUrl {url, source} => {
                 let str_url = url.to_string();
@@ -107,7 +127,8 @@ impl ToString for UrlBits {
                     },
                 }
             },
-        }
+        };
+        f.write_str(&result)
     }
 }
 
@@ -132,15 +153,15 @@ impl <'t> From<Cow<'t, str>> for UrlString<'t> {
     }
 }
 
-impl <'t> ToString for UrlString<'t> {
-    fn to_string(&self) -> String {
+impl <'t> std::fmt::Display for UrlString<'t> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Normalized(url_text) => url_text.clone().into_owned(),
+            Self::Normalized(url_text) => f.write_str(url_text),
             Self::Raw(url_text) => {
                 if url_text.is_empty() {
-                    String::new()
+                    Ok(())
                 } else {
-                    UrlBits::parse(url_text).to_string()
+                    UrlBits::parse(url_text).fmt(f)
                 }
             }
         }
@@ -160,10 +181,58 @@ impl <'t> UrlString<'t> {
     }
 }
 
+/// The parts of a `data:` URL that matter for telling whether
+/// it's acceptable: the media type and the size of the data.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct DataUrl {
+    /// The lowercase MIME type, without the parameters
+    pub mime_type: String,
+    /// The size of the data, once decoded
+    pub size: usize,
+}
+
+impl DataUrl {
+    /// Parses a `data:[<mediatype>][;base64],<data>` URL, returning `None`
+    /// if it's malformed, including the invalid base64 data.
+    pub fn parse(url: &str) -> Option<Self> {
+        let (scheme, rest) = url.split_once(':')?;
+        if !scheme.eq_ignore_ascii_case("data") {
+            return None;
+        }
+        let (header, data) = rest.split_once(',')?;
+        let mime_type = header.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        let is_base64 = header.rsplit(';').next()
+            .filter(|_| header.contains(';'))
+            .is_some_and(|last| last.trim().eq_ignore_ascii_case("base64"));
+        let data: Vec<u8> = percent_decode_str(data).collect();
+        let size = if is_base64 {
+            base64_decoded_size(&data)?
+        } else {
+            data.len()
+        };
+        Some(Self { mime_type, size })
+    }
+}
+
+/// The size of the valid base64 `data` once decoded.
+fn base64_decoded_size(data: &[u8]) -> Option<usize> {
+    let unpadded = data.iter().rposition(|&c| c != b'=').map_or(0, |pos| pos + 1);
+    let padding = data.len() - unpadded;
+    let is_valid = data[..unpadded].iter().all(|c| c.is_ascii_alphanumeric() || *c == b'+' || *c == b'/')
+        && padding <= 2
+        && unpadded % 4 != 1
+        && (padding == 0 || data.len().is_multiple_of(4));
+    if is_valid {
+        Some(unpadded * 3 / 4)
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::borrow::Cow;
-    use crate::urlutils::{UrlBits, UrlString};
+    use crate::urlutils::{DataUrl, UrlBits, UrlString};
 
     #[test]
     fn test_url_bits() {
@@ -182,6 +251,11 @@ mod test {
         let bits = UrlBits::parse("some_page.html?q=Some query#Some text");
         assert!(bits.is_relative());
         assert_eq!(bits.scheme(), "");
+        assert_eq!(bits.host(), None);
+        assert_eq!(UrlBits::parse("https://Example.ORG/a.png").host(), Some("example.org"));
+        assert_eq!(UrlBits::parse("//example.com/a.png").host(), Some("example.com"));
+        assert_eq!(UrlBits::parse("/\\example.com/a.png").host(), Some("example.com"));
+        assert_eq!(UrlBits::parse("data:image/png;base64,AAAA").host(), None);
         assert_eq!(bits.to_string(), "some_page.html?q=Some%20query#Some%20text");
 
         // Another valid relative URL
@@ -203,4 +277,21 @@ mod test {
         assert_eq!(bits.to_string(), "https%3A%3A%3A%3A%2F%2Fexample%2Ecom%2Fsome%5Fpage%2Ehtml%3Fq%3DSome%20query%23Some%20text");
     }
 
+    #[test]
+    fn test_data_url() {
+        assert_eq!(
+            DataUrl::parse("data:Image/GIF;base64,R0lGODlhAQABAAAAACw="),
+            Some(DataUrl { mime_type: "image/gif".into(), size: 14 }));
+        assert_eq!(
+            DataUrl::parse("data:image/png;name=a.png;BASE64,AAAA%2B%2B%2B%2B"),
+            Some(DataUrl { mime_type: "image/png".into(), size: 6 }));
+        assert_eq!(
+            DataUrl::parse("data:,Hello%20World"),
+            Some(DataUrl { mime_type: "".into(), size: 11 }));
+        assert_eq!(DataUrl::parse("data:image/png;base64,AAAAA"), None);
+        assert_eq!(DataUrl::parse("data:image/png;base64,AA=A"), None);
+        assert_eq!(DataUrl::parse("data:image/png;base64,<script>"), None);
+        assert_eq!(DataUrl::parse("data:image/png;base64"), None);
+        assert_eq!(DataUrl::parse("http://example.com/a,b"), None);
+    }
 }
@@ -0,0 +1,649 @@
+//! Tests for the extension points of the parser, which can't be
+//! configured through the YAML fixtures.
+
+use pretty_assertions::assert_str_eq;
+
+use rustextile::{
+    Textile, CustomBlock, CustomBlockOutput, CustomEmoji, SpanRenderer, Entity, EntityKind, GlyphSet, GlyphRule,
+    TableSectionKind, Alignment, VerticalAlignment, TableDiagnostic, TableProblem, FrontMatterValue,
+};
+
+fn bracketing_highlighter(lang: &str, code: &str) -> Option<String> {
+    if lang == "unknown" {
+        None
+    } else {
+        Some(format!("[{0}:{1}]", lang, code))
+    }
+}
+
+#[test]
+fn test_code_highlighter() {
+    let textile = Textile::default()
+        .set_uid("UID")
+        .set_code_highlighter(bracketing_highlighter);
+    let html = textile.parse(concat!(
+        "bc[rust]. let x = 1;\n\n",
+        "bc[unknown]. a < b\n\n",
+        "bc. no language"));
+    assert_str_eq!(
+        html,
+        concat!(
+            "<pre><code class=\"rust\">[rust:let x = 1;]</code></pre>\n\n",
+            "<pre><code class=\"unknown\">a &lt; b</code></pre>\n\n",
+            "<pre><code>no language</code></pre>"));
+
+    // Extended blocks are highlighted as a whole
+    let html = textile.parse(concat!(
+        "bc[rust].. fn main() {\n\n",
+        "    *x* < 1\n",
+        "}\n\n",
+        "p. Done"));
+    assert_str_eq!(
+        html,
+        concat!(
+            "<pre><code class=\"rust\">[rust:fn main() {\n\n",
+            "    *x* < 1\n",
+            "}]</code></pre>\n\n",
+            "<p>Done</p>"));
+
+    // Inline code
+    let html = textile.parse("Call @[rust]x.len()@ or @[unknown]<y>@ or @z@");
+    assert_str_eq!(
+        html,
+        concat!(
+            "<p>Call <code class=\"rust\">[rust:x.len()]</code> or ",
+            "<code>[unknown]&lt;y&gt;</code> or <code>z</code></p>"));
+
+    // Without a highlighter the brackets are a part of the code
+    let html = Textile::default().parse("Call @[rust]x.len()@");
+    assert_str_eq!(html, "<p>Call <code>[rust]x.len()</code></p>");
+}
+
+#[test]
+fn test_code_highlighter_restricted() {
+    // The highlighter must receive the code as it was written
+    let textile = Textile::default()
+        .set_restricted(true)
+        .set_code_highlighter(bracketing_highlighter);
+    let html = textile.parse("bc[html]. <b>\"Tom & Jerry\"</b>\n\n@[html]<i>@");
+    assert_str_eq!(
+        html,
+        concat!(
+            "<pre><code class=\"html\">[html:<b>\"Tom & Jerry\"</b>]</code></pre>\n\n",
+            "<p><code class=\"html\">[html:<i>]</code></p>"));
+}
+
+#[cfg(feature = "syntect")]
+#[test]
+fn test_syntect_highlighter() {
+    let textile = Textile::default()
+        .set_code_highlighter(rustextile::SyntectHighlighter::default());
+    let html = textile.parse("bc[rust]. let s = \"<\";");
+    assert_str_eq!(
+        html,
+        concat!(
+            "<pre><code class=\"rust\"><span class=\"source rust\">",
+            "<span class=\"storage type rust\">let</span> s ",
+            "<span class=\"keyword operator rust\">=</span> ",
+            "<span class=\"string quoted double rust\">",
+            "<span class=\"punctuation definition string begin rust\">&quot;</span>&lt;",
+            "<span class=\"punctuation definition string end rust\">&quot;</span></span>",
+            "<span class=\"punctuation terminator rust\">;</span></span></code></pre>"));
+    let html = textile.parse("bc[no-such-lang]. a < b");
+    assert_str_eq!(html, "<pre><code class=\"no-such-lang\">a &lt; b</code></pre>");
+}
+
+#[test]
+fn test_code_highlighter_with_line_numbers() {
+    let textile = Textile::default()
+        .set_code_highlighter(|_lang: &str, code: &str| {
+            Some(format!("<span class=\"src\">{0}</span>", code))
+        });
+    let html = textile.parse("bc[rust]{numbers: 3; lines: 4}. let x = 1;\nlet y = 2;");
+    assert_str_eq!(
+        html,
+        concat!(
+            "<pre><code class=\"rust\">",
+            "<span class=\"line\"><span class=\"line-number\">3</span>",
+            "<span class=\"src\">let x = 1;</span></span>\n",
+            "<span class=\"line highlighted\"><span class=\"line-number\">4</span>",
+            "<span class=\"src\">let y = 2;</span></span></code></pre>"));
+}
+
+fn textile_with_custom_blocks() -> Textile {
+    Textile::default()
+        .set_uid("UID")
+        .register_block("note", |block| {
+            let mut attributes = block.attributes.to_html_attributes();
+            if let Some(cite) = block.cite {
+                attributes.push(("data-source".into(), cite.into()));
+            }
+            CustomBlockOutput::Textile { element: "aside".into(), attributes }
+        })
+        .register_block("warn", |block| {
+            CustomBlockOutput::Html(
+                format!("<div class=\"warn\" data-extended=\"{0}\">{1}</div>",
+                        block.extended, block.content))
+        })
+        .register_block("warning", |block| {
+            CustomBlockOutput::Html(format!("<strong>{0}</strong>", block.content))
+        })
+}
+
+#[test]
+fn test_custom_blocks() {
+    let textile = textile_with_custom_blocks();
+    let html = textile.parse(concat!(
+        "note(tip#first).:https://example.com Some *important* text\n\n",
+        "warn. Raw *text*\n\n",
+        "warning. Longer signature\n\n",
+        "note.. Extended\n\n",
+        "block\n\n",
+        "p. Regular paragraph"));
+    assert_str_eq!(
+        html,
+        concat!(
+            "<aside class=\"tip\" id=\"first\" data-source=\"https://example.com\">\n",
+            "\t<p>Some <strong>important</strong> text</p>\n",
+            "</aside>\n\n",
+            "<div class=\"warn\" data-extended=\"false\">Raw *text*</div>\n\n",
+            "<strong>Longer signature</strong>\n\n",
+            "<aside>\n",
+            "\t<p>Extended</p>\n\n",
+            "\t<p>block</p>\n",
+            "</aside>\n\n",
+            "<p>Regular paragraph</p>"));
+
+    // Note definitions are still recognized
+    let html = textile.parse("Text[#n]\n\nnote#n. Definition\n\nnotelist.");
+    assert_str_eq!(
+        html,
+        concat!(
+            "<p>Text<sup><a href=\"#noteUID-2\"><span id=\"noterefUID-1\">1</span></a></sup></p>\n\n",
+            "<ol>\n",
+            "\t\t<li><sup><a href=\"#noterefUID-1\">a</a></sup><span id=\"noteUID-2\"> </span>Definition</li>\n",
+            "\t</ol>"));
+
+    // Custom blocks are disabled in the lite mode
+    let textile = textile_with_custom_blocks().set_lite(true);
+    assert_str_eq!(textile.parse("note. Text"), "<p>note. Text</p>");
+}
+
+#[test]
+#[should_panic(expected = "Invalid or reserved block signature: \"bq\"")]
+fn test_custom_block_with_builtin_signature() {
+    let _ = Textile::default()
+        .register_block("bq", |_| CustomBlockOutput::Html(String::new()));
+}
+
+#[test]
+fn test_custom_block_over_optional_builtin() {
+    let handler = |block: &CustomBlock| CustomBlockOutput::Html(format!("<tex>{0}</tex>", block.content));
+    for textile in [
+        Textile::default().set_math(true).register_block("math", handler),
+        Textile::default().register_block("math", handler).set_math(true),
+    ] {
+        assert_str_eq!(textile.parse("math. x^2"), "<tex>x^2</tex>");
+    }
+    let textile = Textile::default().set_details(true).register_block("details", handler);
+    assert_str_eq!(textile.parse("details. Why?\nBecause."), "<tex>Why?\nBecause.</tex>");
+    let textile = Textile::default().register_block("aside", handler);
+    assert_str_eq!(textile.parse("aside. Note"), "<tex>Note</tex>");
+    let textile = Textile::default().set_media(true).register_block("video", handler);
+    assert_str_eq!(textile.parse("video. games"), "<tex>games</tex>");
+    // The extended block still gets its paragraphs one by one
+    let textile = Textile::default().set_data_tables(true).register_block("csv", handler);
+    assert_str_eq!(
+        textile.parse("csv.. a,b\n\nc,d\n\np. Done"),
+        "<tex>a,b</tex>\n\n<tex>c,d</tex>\n\n<p>Done</p>");
+}
+
+fn textile_with_custom_spans() -> Textile {
+    Textile::default()
+        .register_span("==", "==", SpanRenderer::element("mark"))
+        .register_span("[[kbd:", "]]", SpanRenderer::element("kbd"))
+        .register_span("||", "||", SpanRenderer::Callback(Box::new(|span| {
+            let class = span.attributes.class.as_deref().unwrap_or("spoiler");
+            (format!("<span class=\"{0}\" data-len=\"{1}\">", class, span.content.len()),
+             "</span>".to_owned())
+        })))
+}
+
+#[test]
+fn test_custom_spans() {
+    let textile = textile_with_custom_spans();
+    assert_str_eq!(
+        textile.parse("Press [[kbd:Ctrl+C]] to ==copy *this*==."),
+        "<p>Press <kbd>Ctrl+C</kbd> to <mark>copy <strong>this</strong></mark>.</p>");
+    assert_str_eq!(
+        textile.parse("An ==(hot#x){color:red}[en]important== and a ||secret|| and ||(big)one||"),
+        concat!(
+            "<p>An <mark class=\"hot\" id=\"x\" lang=\"en\" style=\"color:red;\">important</mark> ",
+            "and a <span class=\"spoiler\" data-len=\"6\">secret</span> ",
+            "and <span class=\"big\" data-len=\"3\">one</span></p>"));
+    // Built-in spans can contain the custom ones
+    assert_str_eq!(
+        textile.parse("_an ==important== word_"),
+        "<p><em>an <mark>important</mark> word</em></p>");
+    // Markers inside words and code are left alone
+    assert_str_eq!(
+        textile.parse("a==b== and @==c==@"),
+        "<p>a==b== and <code>==c==</code></p>");
+
+    // Without the registration "==" keeps disabling textile
+    assert_str_eq!(
+        Textile::default().parse("==*raw*=="),
+        "<p>*raw*</p>");
+}
+
+#[test]
+fn test_custom_span_element_attributes() {
+    let textile = Textile::default()
+        .register_span("!!", "!!", SpanRenderer::Element {
+            name: "span".into(),
+            attributes: vec![("class".into(), "alert".into()), ("role".into(), "alert".into())],
+        });
+    assert_str_eq!(
+        textile.parse("A !!(loud)warning!!"),
+        "<p>A <span class=\"alert loud\" role=\"alert\">warning</span></p>");
+}
+
+#[test]
+fn test_math_renderer() {
+    let textile = Textile::default()
+        .set_restricted(true)
+        .set_math_renderer(|tex: &str, display: bool| {
+            if tex.contains("error") {
+                None
+            } else {
+                Some(format!("<math display=\"{0}\">{1}</math>",
+                             if display {"block"} else {"inline"}, tex))
+            }
+        });
+    let html = textile.parse("Take $a<b$ and $error$\n\nmath. x_1 > y");
+    assert_str_eq!(
+        html,
+        concat!(
+            "<p>Take <span class=\"math\"><math display=\"inline\">a<b</math></span> ",
+            "and <span class=\"math\">\\(error\\)</span></p>\n\n",
+            "<div class=\"math\"><math display=\"block\">x_1 > y</math></div>"));
+}
+
+#[test]
+fn test_custom_emoji() {
+    let textile = Textile::default()
+        .set_restricted(true)
+        .add_emoji("parrot", CustomEmoji::Image("https://example.com/parrot.gif?a=1&b=2".into()))
+        .add_emoji("evil", CustomEmoji::Image("javascript:alert(1)".into()))
+        .add_emoji("lt", CustomEmoji::Text("<".into()));
+    let html = textile.parse(":parrot: :evil: :lt: \"Go :parrot:\":https://example.com");
+    assert_str_eq!(
+        html,
+        concat!(
+            "<p><img alt=\":parrot:\" class=\"emoji\" src=\"https://example.com/parrot.gif?a=1&amp;b=2\" /> ",
+            ":evil: &lt; ",
+            "<a href=\"https://example.com/\">Go ",
+            "<img alt=\":parrot:\" class=\"emoji\" src=\"https://example.com/parrot.gif?a=1&amp;b=2\" /></a></p>"));
+}
+
+#[test]
+fn test_custom_emoji_from_other_hosts() {
+    let textile = Textile::default()
+        .set_image_hosts(Some(&["cdn.example.com"]))
+        .add_emoji("parrot", CustomEmoji::Image("https://cdn.example.com/parrot.gif".into()))
+        .add_emoji("pixel", CustomEmoji::Image("https://tracker.example.org/pixel.gif".into()));
+    assert_str_eq!(
+        textile.parse(":parrot: :pixel:"),
+        "<p><img alt=\":parrot:\" class=\"emoji\" src=\"https://cdn.example.com/parrot.gif\" /> :pixel:</p>");
+}
+
+#[test]
+#[should_panic(expected = "Invalid emoji shortcode: \"a b\"")]
+fn test_invalid_emoji_shortcode() {
+    let _ = Textile::default().add_emoji("a b", CustomEmoji::Text("?".into()));
+}
+
+fn textile_with_entities() -> Textile {
+    Textile::default()
+        .set_uid("UID")
+        .set_entity_resolver(|kind: EntityKind, name: &str| {
+            match (kind, name) {
+                (EntityKind::Mention, "alice" | "bob" | "x") => Some(format!("/u/{0}", name)),
+                (EntityKind::Hashtag, "release-2" | "n") => Some(format!("/t/{0}?a=1&b=2", name)),
+                _ => None,
+            }
+        })
+}
+
+#[test]
+fn test_mentions_and_hashtags() {
+    let textile = textile_with_entities();
+    let document = textile.parse_document(concat!(
+        "Ping @alice and @bob (@alice.) about #release-2, not @carol or #other.\n\n",
+        "# item #release-2\n",
+        "# *@bob*\n\n",
+        "Mail alice@example.com, see @x@ and @x.len()@, read[#n]\n\n",
+        "\"@alice\":https://example.com <span title=\"@bob\">#n</span>\n\n",
+        "note#n. The note\n\n",
+        "notelist."));
+    assert_str_eq!(
+        document.html,
+        concat!(
+            "<p>Ping <a class=\"mention\" href=\"/u/alice\">@alice</a> and ",
+            "<a class=\"mention\" href=\"/u/bob\">@bob</a> ",
+            "(<a class=\"mention\" href=\"/u/alice\">@alice</a>.) about ",
+            "<a class=\"hashtag\" href=\"/t/release-2?a=1&amp;b=2\">#release-2</a>, ",
+            "not @carol or #other.</p>\n\n",
+            "<ol>\n",
+            "\t<li>item <a class=\"hashtag\" href=\"/t/release-2?a=1&amp;b=2\">#release-2</a></li>\n",
+            "\t<li><strong><a class=\"mention\" href=\"/u/bob\">@bob</a></strong></li>\n",
+            "</ol>\n\n",
+            "<p>Mail alice@example.com, see <code>x</code> and <code>x.len()</code>, ",
+            "read<sup><a href=\"#noteUID-2\"><span id=\"noterefUID-1\">1</span></a></sup></p>\n\n",
+            "<p><a href=\"https://example.com/\">@alice</a> ",
+            "<span title=\"@bob\"><a class=\"hashtag\" href=\"/t/n?a=1&amp;b=2\">#n</a></span></p>\n\n",
+            "<ol>\n",
+            "\t\t<li><sup><a href=\"#noterefUID-1\">a</a></sup><span id=\"noteUID-2\"> </span>The note</li>\n",
+            "\t</ol>"));
+    assert_eq!(
+        document.entities,
+        vec![
+            Entity { kind: EntityKind::Mention, name: "alice".into(), url: "/u/alice".into() },
+            Entity { kind: EntityKind::Mention, name: "bob".into(), url: "/u/bob".into() },
+            Entity {
+                kind: EntityKind::Hashtag,
+                name: "release-2".into(),
+                url: "/t/release-2?a=1&b=2".into(),
+            },
+            Entity { kind: EntityKind::Hashtag, name: "n".into(), url: "/t/n?a=1&b=2".into() },
+        ]);
+
+    // Without a resolver nothing changes
+    assert_str_eq!(Textile::default().parse("@alice and @bob"), "<p><code>alice and </code>bob</p>");
+}
+
+#[test]
+fn test_glyph_set() {
+    let input = "A 4x4 NASA car (C) -- \"quoted\" and '\"' with it's ABC(A Big Company)...";
+    let textile = Textile::default()
+        .set_glyph_set(
+            GlyphSet::default()
+                .disable(GlyphRule::DimensionSign)
+                .disable(GlyphRule::Copyright)
+                .disable(GlyphRule::Caps)
+                .disable(GlyphRule::DoubleQuotes));
+    assert_str_eq!(
+        textile.parse(input),
+        concat!(
+            "<p>A 4x4 NASA car (C) &#8212; \"quoted\" and &#8216;\"&#8217; with it&#8217;s ",
+            "<abbr title=\"A Big Company\">ABC</abbr>&#8230;</p>"));
+    // Neither are the quoted quotes
+    assert_str_eq!(textile.parse("A \"\"\" quote"), "<p>A \"\"\" quote</p>");
+
+    let textile = Textile::default()
+        .set_glyph_set(GlyphSet::none().enable(GlyphRule::Caps));
+    assert_str_eq!(
+        textile.parse("A 4x4 NASA car (C) -- it's ABC(A Big Company)..."),
+        concat!(
+            "<p>A 4x4 <span class=\"caps\">NASA</span> car (C) -- ",
+            "it's <span class=\"caps\">ABC</span>(A Big Company)...</p>"));
+}
+
+#[test]
+fn test_custom_glyph_rules() {
+    let glyphs = GlyphSet::default()
+        .add_rule(r"-&gt;", "&#8594;")
+        .add_rule(r"\[(\w+)\]", "<b>$1</b>");
+    let textile = Textile::default().set_glyph_set(glyphs.clone());
+    assert_str_eq!(
+        textile.parse("a -> b, [note] <i>[x]</i>"),
+        "<p>a &#8594; b, <b>note</b> <i><b>x</b></i></p>");
+
+    // Whatever the rules capture in restricted mode is still encoded
+    let textile = Textile::default()
+        .set_restricted(true)
+        .set_glyph_set(glyphs.add_rule(r"\{(.+?)\}", "<code>$1</code>"));
+    assert_str_eq!(
+        textile.parse("a -> b, {<script>}"),
+        "<p>a &#8594; b, <code>&lt;script&gt;</code></p>");
+}
+
+#[test]
+#[should_panic(expected = "Invalid glyph rule pattern")]
+fn test_invalid_glyph_rule() {
+    let _ = GlyphSet::default().add_rule(r"(unclosed", "");
+}
+
+#[test]
+fn test_table_model() {
+    let input = concat!(
+        "table(prices). Price list\n",
+        "|=. Prices, \"see more\":https://example.com/\n",
+        "|:\\2. | 100 | 50 |\n",
+        "|_. Item |_. Price |\n",
+        "|~.\n",
+        "|\\2>. Total: *20* |\n",
+        "|-.\n",
+        "|/2. Tea (TM) |^>. 5 |\n",
+        "|{text-align: center}. 15\nor less |\n",
+        "\n",
+        "| second | table |");
+    let textile = Textile::default().set_uid("UID");
+    let document = textile.parse_document(input);
+    assert_eq!(document.tables.len(), 2);
+
+    let table = &document.tables[0];
+    assert_eq!(table.attributes.class.as_deref(), Some("prices"));
+    assert_eq!(table.summary.as_deref(), Some("Price list"));
+    assert_str_eq!(
+        table.caption.as_ref().unwrap().content,
+        "Prices, <a href=\"https://example.com/\">see more</a>");
+    assert_eq!(table.column_groups.len(), 1);
+    assert_eq!(table.column_groups[0].attributes.span.as_deref(), Some("2"));
+    let widths: Vec<_> = table.column_groups[0].columns.iter().map(|c| c.width.as_deref()).collect();
+    assert_eq!(widths, [Some("100"), Some("50")]);
+
+    let header = &table.rows[0];
+    assert!(header.cells.iter().all(|cell| cell.header));
+    assert_str_eq!(header.cells[1].content, "Price ");
+
+    let kinds: Vec<_> = table.sections.iter().map(|s| s.kind).collect();
+    assert_eq!(kinds, [TableSectionKind::Foot, TableSectionKind::Body]);
+    let total = &table.sections[0].rows[0].cells[0];
+    assert_eq!(total.colspan(), 2);
+    assert_eq!(total.rowspan(), 1);
+    assert_eq!(total.alignment(), Some(Alignment::Right));
+    assert_str_eq!(total.content, "Total: <strong>20</strong> ");
+
+    let body = &table.sections[1].rows;
+    assert_eq!(body[0].cells[0].rowspan(), 2);
+    assert_eq!(body[0].cells[1].vertical_alignment(), Some(VerticalAlignment::Top));
+    assert_eq!(body[0].cells[1].alignment(), Some(Alignment::Right));
+    assert_str_eq!(body[0].cells[0].content, "Tea &#8482; ");
+    assert_eq!(body[1].cells[0].alignment(), Some(Alignment::Center));
+    assert_str_eq!(body[1].cells[0].content, "15<br>\nor less ");
+
+    // Rendering the model gives back the same HTML
+    let rendered: Vec<_> = document.tables.iter().map(|t| format!("\t{0}", t.to_html())).collect();
+    assert_str_eq!(document.html, rendered.join("\n\n"));
+
+    // Changes to the model are reflected in the HTML
+    let mut table = document.tables[1].clone();
+    table.rows[0].cells[1].header = true;
+    table.rows[0].cells[1].content = "<em>changed</em>".to_owned();
+    assert_str_eq!(
+        table.to_html(),
+        "<table>\n\t\t<tr>\n\t\t\t<td> second </td>\n\t\t\t<th><em>changed</em></th>\n\t\t</tr>\n\t</table>");
+
+    // The tables not making it into the output are left out
+    let document = textile.parse_document("notextile. | not | a table |\n\n| a | table |");
+    assert_eq!(document.tables.len(), 1);
+    assert_str_eq!(document.tables[0].rows[0].cells[0].content, " a ");
+
+    // Neither the nested tables nor the content kept intact confuse the model
+    let document = textile.parse_document(concat!(
+        "| <table><tr><td>inner</td></tr></table> after | ==*raw* </td>== |\n\n",
+        "| @a</td>b@ | *b* |"));
+    assert_eq!(document.tables.len(), 2);
+    let cells = &document.tables[0].rows[0].cells;
+    assert_str_eq!(cells[0].content, " <table><tr><td>inner</td></tr></table> after ");
+    assert_str_eq!(cells[1].content, " *raw* </td> ");
+    let cells = &document.tables[1].rows[0].cells;
+    assert_str_eq!(cells[0].content, " <code>a&lt;/td&gt;b</code> ");
+    assert_str_eq!(cells[1].content, " <strong>b</strong> ");
+    let rendered: Vec<_> = document.tables.iter().map(|t| format!("\t{0}", t.to_html())).collect();
+    assert_str_eq!(document.html, rendered.join("\n\n"));
+
+    // The sanitizer cleans the content of the model too
+    let document = Textile::default()
+        .set_sanitize(true)
+        .parse_document("| <script>alert(1)</script>safe |");
+    assert_str_eq!(document.tables[0].rows[0].cells[0].content, " safe ");
+}
+
+#[test]
+fn test_table_diagnostics() {
+    let input = concat!(
+        "|^.\n|_. A|_. B|_. C|\n|-.\n|/3. x|y|z\n|\\0. p|q|\n|r|/9. s|\n|t\n\n",
+        "notextile. |a|\n|b|c|\n\n",
+        "|a|\\99999999999999999999999. b|\n");
+    let textile = Textile::default().set_normalize_tables(true);
+    let document = textile.parse_document(input);
+    assert_eq!(document.tables.len(), 2);
+    // The offset is that of the row starting with the given text
+    let diagnostic = |table, row, column, row_start: &str, problem| {
+        TableDiagnostic { table, row, column, offset: input.find(row_start).unwrap(), problem }
+    };
+    assert_eq!(
+        document.table_diagnostics,
+        vec![
+            diagnostic(0, 1, 3, "|/3. x", TableProblem::MissingTrailingPipe),
+            diagnostic(0, 2, 1, "|\\0. p", TableProblem::ZeroSpan),
+            diagnostic(0, 3, 2, "|r|", TableProblem::RowspanOverflow { rowspan: 9, clamped_to: 2 }),
+            diagnostic(0, 4, 1, "|t\n", TableProblem::MissingTrailingPipe),
+            diagnostic(0, 4, 1, "|t\n", TableProblem::MissingCells { count: 1 }),
+            // The table within notextile is not counted
+            diagnostic(
+                1, 0, 1, "|a|\\9",
+                TableProblem::ColspanOverflow { colspan: usize::MAX, clamped_to: 1 }),
+        ]);
+    assert_eq!(
+        document.table_diagnostics[4].to_string(),
+        "Table 1, row 5, column 2: the row lacks a cell");
+    assert_eq!(document.tables[1].rows[0].cells[1].colspan(), 1);
+
+    // Nothing is reported without normalization
+    let document = Textile::default().parse_document(input);
+    assert!(document.table_diagnostics.is_empty());
+
+    // The offsets are within the whole source text
+    let input = concat!(
+        "---\r\ntitle: Tables\r\n---\r\n\r\n",
+        "table(x). Summary\r\n|=. Caption\r\n|:. 100 |\r\n| a | b |\r\n| c |\r\n\r\n",
+        "bq.. Quote\r\n\r\n| d | e |\r\n|\\3. f |\r\n\r\n",
+        "csv. e,f\r\n\"g\r\nh\",i,j\r\n");
+    for restricted in [false, true] {
+        let textile = Textile::default()
+            .set_normalize_tables(true)
+            .set_data_tables(true)
+            .set_front_matter(true)
+            .set_restricted(restricted);
+        let document = textile.parse_document(input);
+        let diagnostics: Vec<_> = document.table_diagnostics.iter()
+            .map(|d| (d.table, d.row, &input[d.offset..d.offset + 3], d.problem))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (0, 1, "| c", TableProblem::MissingCells { count: 1 }),
+                (1, 1, "|\\3", TableProblem::ColspanOverflow { colspan: 3, clamped_to: 2 }),
+                (2, 0, "csv", TableProblem::MissingCells { count: 1 }),
+            ]);
+    }
+}
+
+#[test]
+fn test_task_offsets() {
+    let input = concat!(
+        " \r\n\r\nChores:\r\n\r\n* [x] Dishes & <b>cups</b>\r\n** [ ] Laundry\r\n\r\n",
+        "bc.. Code\n\n* [ ] Not a task\n\np. Done\n\n",
+        "|a|b\n* [ ] Not a task either|\n\n",
+        "#(urgent) [X]  Taxes\n");
+    for restricted in [false, true] {
+        let textile = Textile::default().set_task_lists(true).set_restricted(restricted);
+        let document = textile.parse_document(input);
+        let tasks: Vec<_> = document.tasks.iter()
+            .map(|task| (task.text.as_str(), task.checked, &input[task.offset..task.offset + 3]))
+            .collect();
+        assert_eq!(
+            tasks,
+            vec![
+                ("Dishes & <b>cups</b>", true, "[x]"),
+                ("Laundry", false, "[ ]"),
+                ("Taxes", true, "[X]"),
+            ]);
+    }
+
+    // The tasks are not recognized unless enabled
+    let document = Textile::default().parse_document(input);
+    assert!(document.tasks.is_empty());
+
+    // The tasks of the lists nested within the items are found too
+    let input = "* [ ] Outer\r\n\r\n  * [x] Inner\r\n\r\n* [ ] Next";
+    let document = Textile::default().set_task_lists(true).set_list_blocks(true).parse_document(input);
+    let offsets: Vec<_> = document.tasks.iter().map(|task| task.offset).collect();
+    assert_eq!(offsets, vec![2, 19, 34]);
+
+    // The offsets are within the whole text, front matter included
+    let input = "---\ntitle: Chores\n---\n\n* [ ] Dishes";
+    let document = Textile::default().set_task_lists(true).set_front_matter(true).parse_document(input);
+    assert_eq!(document.tasks[0].offset, input.find('[').unwrap());
+
+    // Along with the Windows line breaks and the nested blocks
+    let input = concat!(
+        "---\r\ntitle: Chores\r\ntags: [home]\r\n---\r\n \r\n\r\n",
+        "* [ ] Dishes & cups\r\n\r\n  Some text\r\n\r\n  * [x] Inner\r\n\r\n",
+        "* [ ] Laundry\r\n\r\nbq.. Quote\r\n\r\n# [X] Taxes\r\n");
+    for restricted in [false, true] {
+        let textile = Textile::default()
+            .set_task_lists(true)
+            .set_list_blocks(true)
+            .set_front_matter(true)
+            .set_restricted(restricted);
+        let document = textile.parse_document(input);
+        let tasks: Vec<_> = document.tasks.iter()
+            .map(|task| (task.text.as_str(), &input[task.offset..task.offset + 3]))
+            .collect();
+        assert_eq!(
+            tasks,
+            vec![("Dishes & cups", "[ ]"), ("Inner", "[x]"), ("Laundry", "[ ]"), ("Taxes", "[X]")]);
+        let offsets: Vec<_> = document.tasks.iter().map(|task| task.offset).collect();
+        assert_eq!(offsets, vec![46, 86, 101, 132]);
+    }
+}
+
+#[test]
+fn test_front_matter() {
+    let input = "---\ntitle: \"Tom & Jerry\"\ntags:\n  - cats\n  - mice\n---\n\nh1. Hi\n";
+    for restricted in [false, true] {
+        let textile = Textile::default().set_front_matter(true).set_restricted(restricted);
+        let document = textile.parse_document(input);
+        assert_eq!(document.html, "<h1>Hi</h1>");
+        assert_eq!(document.front_matter["title"], FrontMatterValue::Text("Tom & Jerry".into()));
+        assert_eq!(
+            document.front_matter["tags"],
+            FrontMatterValue::List(vec!["cats".into(), "mice".into()]));
+    }
+
+    // Only the front matter
+    let document = Textile::default().set_front_matter(true).parse_document("---\ndraft: yes\n---\n");
+    assert_eq!(document.html, "");
+    assert_eq!(document.front_matter["draft"].as_text(), Some("yes"));
+
+    // Not recognized unless enabled, or elsewhere than at the start
+    let document = Textile::default().parse_document(input);
+    assert!(document.front_matter.is_empty());
+    assert!(document.html.contains("title:"));
+    let document = Textile::default().set_front_matter(true).parse_document("Hi\n\n---\na: b\n---");
+    assert!(document.front_matter.is_empty());
+}
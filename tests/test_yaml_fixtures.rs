@@ -17,6 +17,27 @@ struct ParserSettings {
     setGettingImageSize: Option<bool>,
     setHtmlType: Option<String>,
     setBlockTags: Option<bool>,
+    setMath: Option<bool>,
+    setDataTables: Option<bool>,
+    setDetails: Option<bool>,
+    setContainers: Option<bool>,
+    setFigures: Option<bool>,
+    setMedia: Option<bool>,
+    setListBlocks: Option<bool>,
+    setEmoji: Option<bool>,
+    setExtraSpans: Option<bool>,
+    setTypographyLocale: Option<String>,
+    setUtf8Glyphs: Option<bool>,
+    setNormalizeTables: Option<bool>,
+    setHtml5Tables: Option<bool>,
+    setTableSummary: Option<String>,
+    setTableWrapper: Option<String>,
+    setTaskLists: Option<bool>,
+    setInteractiveTasks: Option<bool>,
+    setFigureNumbering: Option<String>,
+    setResponsiveImages: Option<String>,
+    setImageHosts: Option<Vec<String>>,
+    setImageFallback: Option<String>,
 }
 
 impl ParserSettings {
@@ -49,6 +70,84 @@ impl ParserSettings {
         if let Some(value) = self.setBlockTags {
             parser = parser.set_block_tags(value);
         }
+        if let Some(value) = self.setMath {
+            parser = parser.set_math(value);
+        }
+        if let Some(value) = self.setDataTables {
+            parser = parser.set_data_tables(value);
+        }
+        if let Some(value) = self.setDetails {
+            parser = parser.set_details(value);
+        }
+        if let Some(value) = self.setContainers {
+            parser = parser.set_containers(value);
+        }
+        if let Some(value) = self.setFigures {
+            parser = parser.set_figures(value);
+        }
+        if let Some(value) = self.setMedia {
+            parser = parser.set_media(value);
+        }
+        if let Some(value) = self.setListBlocks {
+            parser = parser.set_list_blocks(value);
+        }
+        if let Some(value) = self.setEmoji {
+            parser = parser.set_emoji(value);
+        }
+        if let Some(value) = self.setExtraSpans {
+            parser = parser.set_extra_spans(value);
+        }
+        if let Some(ref value) = self.setTypographyLocale {
+            parser = parser.set_typography_locale(match value.as_str() {
+                "en" => rustextile::TypographyLocale::English,
+                "de" => rustextile::TypographyLocale::German,
+                "fr" => rustextile::TypographyLocale::French,
+                _ => panic!("Unsupported typography locale: {}", value),
+            });
+        }
+        if let Some(value) = self.setUtf8Glyphs {
+            parser = parser.set_utf8_glyphs(value);
+        }
+        if let Some(value) = self.setNormalizeTables {
+            parser = parser.set_normalize_tables(value);
+        }
+        if let Some(value) = self.setHtml5Tables {
+            parser = parser.set_html5_tables(value);
+        }
+        if let Some(ref value) = self.setTableSummary {
+            parser = parser.set_table_summary(match value.as_str() {
+                "caption" => rustextile::TableSummary::Caption,
+                "description" => rustextile::TableSummary::Description,
+                _ => panic!("Unsupported table summary: {}", value),
+            });
+        }
+        if let Some(ref value) = self.setTableWrapper {
+            parser = parser.set_table_wrapper(Some(value));
+        }
+        if let Some(value) = self.setTaskLists {
+            parser = parser.set_task_lists(value);
+        }
+        if let Some(value) = self.setInteractiveTasks {
+            parser = parser.set_interactive_tasks(value);
+        }
+        if let Some(ref value) = self.setFigureNumbering {
+            parser = parser.set_figure_numbering(Some(value));
+        }
+        if let Some(ref value) = self.setResponsiveImages {
+            parser = parser.set_responsive_images(
+                rustextile::ResponsiveImages::with_template(value, &[400, 800]));
+        }
+        if let Some(ref value) = self.setImageHosts {
+            let hosts: Vec<&str> = value.iter().map(String::as_str).collect();
+            parser = parser.set_image_hosts(Some(&hosts));
+        }
+        if let Some(ref value) = self.setImageFallback {
+            parser = parser.set_image_fallback(match value.as_str() {
+                "link" => rustextile::ImageFallback::Link,
+                "placeholder" => rustextile::ImageFallback::Placeholder,
+                _ => panic!("Unsupported image fallback: {}", value),
+            });
+        }
         parser
     }
 }
@@ -56,7 +155,7 @@ impl ParserSettings {
 /// YAML contains chunks like "\x20" which, although totally valid,
 /// for some reason are not recognized by serde_yaml at the moment,
 /// and have to be converted into their respective characters by this function.
-fn replace_xcodes(text: &str) -> Cow<str> {
+fn replace_xcodes(text: &str) -> Cow<'_, str> {
     lazy_static! {
         static ref XCODE: Regex = Regex::new(r"\\x(\d{2})").unwrap();
     }
@@ -154,10 +253,28 @@ fn test_yaml_fixtures() {
         "limits",
         "basic",
         "codeblocks",
+        "code-layout",
+        "csv-tables",
+        "table-normalization",
+        "html5-tables",
+        "task-lists",
+        "list-blocks",
+        "details",
+        "containers",
+        "figures",
+        "media",
+        "responsive-images",
+        "image-hosts",
+        "data-urls",
+        "math",
+        "emoji",
+        "typography",
+        "utf8-glyphs",
         "images",
         "links",
         "dividers",
         "inline-code",
+        "inline-elements",
         "span-wrappers",
         "issue-22",
         "issue-24",